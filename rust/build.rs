@@ -1,89 +1,206 @@
 extern crate prost_build;
 
-use std::{
-    env,
-    io::{Cursor, Result},
-    time::Duration,
-};
+use std::{env, io::Result, path::PathBuf};
 extern crate reqwest;
 use reqwest::{header::USER_AGENT, Url};
+use sha2::{Digest, Sha256};
+use similar::TextDiff;
+
+#[path = "src/protocol_selection.rs"]
+mod protocol_selection;
 
 const DCL_PROTOCOL_REPO_URL: &str =
-    "https://api.github.com/repos/decentraland/protocol/contents/proto/decentraland";
-const FRIENDSHIP_PROTO_PATH: &str = "/social/friendships/friendships.proto";
-/// Modify this value to update the proto version, it is the commit sha from protocol repo used for downloading the proto file
-const FRIENDSHIPS_PROTOCOL_VERSION: &str = "c14d71c3f1f0393369b65667dddd6c59136f45e5";
+    "https://api.github.com/repos/decentraland/protocol/contents/proto";
 const EXTERNAL_DEFINITIONS_FOLDER: &str = "ext-proto";
-const EXT_FRIENDSHIPS_PROTO_FILE: &str = "ext-proto/friendships.proto";
 
-fn main() -> Result<()> {
-    if should_download_proto() {
-        download_proto_from_github()?;
-    }
+/// A proto of the protocol repo pinned at a commit, vendored in `ext-proto/<module>` with the same
+/// paths as in the repo and built into the `<module>` module of the crate when its feature is
+/// enabled.
+struct ProtoDefinition {
+    module: &'static str,
+    feature: &'static str,
+    /// Whether it's a version of the friendships protocol, one the crate can use, see
+    /// [`PROTOCOL_VAR`]
+    friendships: bool,
+    /// The commit sha from protocol repo used for downloading the proto files
+    commit: &'static str,
+    /// The proto the clients are generated from, then the ones it imports, all of them compiled
+    files: &'static [ProtoFile],
+}
+
+struct ProtoFile {
+    /// The path under `proto/` in the protocol repo
+    path: &'static str,
+    /// SHA-256 of the file at the pinned commit, add the one of a new version after reviewing the
+    /// diff printed by the build
+    checksum: &'static str,
+}
+
+/// Add a proto, or another version of one, here with its feature in `Cargo.toml` and its module in
+/// `lib.rs` to build it side by side with the others. Each version of the friendships protocol also
+/// needs its `pub use <module> as protocol` in `lib.rs`, see [`PROTOCOL_VAR`].
+const PROTOS: &[ProtoDefinition] = &[ProtoDefinition {
+    module: "v1",
+    feature: "protocol-v1",
+    friendships: true,
+    commit: "c14d71c3f1f0393369b65667dddd6c59136f45e5",
+    files: &[ProtoFile {
+        path: "decentraland/social/friendships/friendships.proto",
+        checksum: "9932dd9dd12fc086b1784e7a34010e7e2ac50ba8c55b138f5e8dac3bd448a5e0",
+    }],
+}];
+
+/// Set to download the protos at their pinned commits instead of building the vendored ones
+const REFRESH_PROTO_VAR: &str = "SOCIAL_CLIENT_REFRESH_PROTO";
+/// The version of the friendships protocol the crate uses when several are enabled, e.g. `v1`,
+/// the last enabled one of `PROTOS`, which lists them oldest first, by default
+const PROTOCOL_VAR: &str = "SOCIAL_CLIENT_PROTOCOL";
 
-    // Tell Cargo that if the given file changes, to rerun this build script.
-    println!("cargo:rerun-if-changed=ext-proto/friendships.proto");
+fn main() -> Result<()> {
+    println!("cargo:rerun-if-env-changed={REFRESH_PROTO_VAR}");
+    println!("cargo:rerun-if-env-changed={PROTOCOL_VAR}");
+    select_protocol();
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR").expect("OUT_DIR is set by cargo"));
+    for definition in PROTOS.iter().filter(|definition| definition.is_enabled()) {
+        for file in definition.files {
+            if should_download_proto() {
+                download_proto_from_github(definition, file)?;
+            }
+            // Tell Cargo that if the given file changes, to rerun this build script.
+            println!("cargo:rerun-if-changed={}", definition.vendored_path(file));
+        }
 
-    let mut prost_config = prost_build::Config::new();
-    prost_config.protoc_arg("--experimental_allow_proto3_optional");
-    prost_config.service_generator(Box::new(dcl_rpc::codegen::RPCServiceGenerator::new()));
-    prost_config.compile_protos(
-        &[EXT_FRIENDSHIPS_PROTO_FILE],
-        &[EXTERNAL_DEFINITIONS_FOLDER],
-    )?;
+        // Versions of a proto have the same package, so each one is generated in its own folder
+        let module_out_dir = out_dir.join(definition.module);
+        std::fs::create_dir_all(&module_out_dir)?;
+
+        let mut prost_config = prost_build::Config::new();
+        prost_config.out_dir(module_out_dir);
+        prost_config.protoc_arg("--experimental_allow_proto3_optional");
+        // Gives the JSON output its field names, e.g. `{"body":{"request":{"created_at":...}}}`
+        prost_config.type_attribute(".", "#[derive(serde::Serialize)]");
+        prost_config.type_attribute(".", "#[serde(rename_all = \"snake_case\")]");
+        // The token authenticating the requests never ends up in the output
+        prost_config.field_attribute("Payload.synapse_token", "#[serde(skip)]");
+        prost_config.service_generator(Box::new(dcl_rpc::codegen::RPCServiceGenerator::new()));
+        // Each package is generated in its own file, the ones of the imported protos too
+        let files: Vec<String> = definition
+            .files
+            .iter()
+            .map(|file| definition.vendored_path(file))
+            .collect();
+        prost_config.compile_protos(&files, &[definition.folder()])?;
+    }
     Ok(())
 }
 
-/// Avoid the GitHub Request if the file exists and has been modified in the last hour.
-/// It will return `true` if the file has not been modified in the last hour or doesn't exist.
-/// If the file has been modified within the last hour, the function will return `false`.
-fn should_download_proto() -> bool {
-    if let Ok(cwd) = env::current_dir() {
-        let path = cwd.join(EXT_FRIENDSHIPS_PROTO_FILE);
-        if let Ok(metadata) = std::fs::metadata(path) {
-            if let Ok(modified) = metadata.modified() {
-                if modified
-                    .elapsed()
-                    .unwrap_or_else(|_| Duration::from_secs(0))
-                    < Duration::from_secs(3600)
-                {
-                    return false;
-                }
-            }
+/// Set the `protocol` cfg to the module of the friendships protocol version the crate re-exports,
+/// and `PROTOCOL_VERSION` in `lib.rs` to its name.
+fn select_protocol() {
+    let versions = PROTOS.iter().filter(|definition| definition.friendships);
+    let values: Vec<String> = versions
+        .map(|definition| format!("\"{}\"", definition.module))
+        .collect();
+    println!(
+        "cargo:rustc-check-cfg=cfg(protocol, values({}))",
+        values.join(", ")
+    );
+
+    let enabled: Vec<&str> = PROTOS
+        .iter()
+        .filter(|definition| definition.friendships && definition.is_enabled())
+        .map(|definition| definition.module)
+        .collect();
+    let requested = env::var(PROTOCOL_VAR)
+        .ok()
+        .filter(|module| !module.is_empty());
+    match protocol_selection::select_protocol(&enabled, requested.as_deref()) {
+        Ok(Some(selected)) => {
+            println!("cargo:rustc-cfg=protocol=\"{selected}\"");
+            println!("cargo:rustc-env=SOCIAL_CLIENT_SELECTED_PROTOCOL={selected}");
         }
+        // lib.rs fails to compile without any version
+        Ok(None) => {}
+        Err(err) => panic!("{PROTOCOL_VAR} {err}"),
+    }
+}
+
+impl ProtoDefinition {
+    fn is_enabled(&self) -> bool {
+        let feature = self.feature.to_uppercase().replace('-', "_");
+        env::var_os(format!("CARGO_FEATURE_{feature}")).is_some()
+    }
+
+    fn folder(&self) -> String {
+        format!("{EXTERNAL_DEFINITIONS_FOLDER}/{}", self.module)
+    }
+
+    fn vendored_path(&self, file: &ProtoFile) -> String {
+        format!("{}/{}", self.folder(), file.path)
     }
-    true
 }
 
-fn download_proto_from_github() -> Result<()> {
+/// The protos are vendored in `ext-proto` so builds work offline, they're only downloaded again
+/// when asked to with the `refresh-proto` feature or a non-empty `SOCIAL_CLIENT_REFRESH_PROTO`.
+fn should_download_proto() -> bool {
+    env::var_os("CARGO_FEATURE_REFRESH_PROTO").is_some()
+        || env::var(REFRESH_PROTO_VAR).is_ok_and(|value| !value.is_empty() && value != "0")
+}
+
+fn download_proto_from_github(definition: &ProtoDefinition, file: &ProtoFile) -> Result<()> {
     let client = reqwest::blocking::Client::new();
 
-    let file_url = build_github_url_to_download();
+    let file_url = build_github_url_to_download(file.path, definition.commit);
     let file_metadata = get_file_info(&client, file_url);
 
     let content_url = extract_file_url(file_metadata);
-    let content = download_file(client, content_url);
+    let content = match download_file(client, content_url).text() {
+        Ok(content) => content,
+        Err(err) => panic!("There was an error reading content, {err}"),
+    };
 
-    save_content_to_file(content)
+    let vendored_path = definition.vendored_path(file);
+    verify_checksum(definition, file, &vendored_path, &content);
+    save_content_to_file(&vendored_path, &content)
 }
 
-fn save_content_to_file(content: reqwest::blocking::Response) -> Result<()> {
-    let cwd = env::current_dir()?;
-    // Create folder if missing
-    std::fs::create_dir_all(
-        String::from(cwd.to_string_lossy()) + "/" + EXTERNAL_DEFINITIONS_FOLDER,
-    )?;
-
-    let file_path: String = String::from(cwd.to_string_lossy()) + "/" + EXT_FRIENDSHIPS_PROTO_FILE;
-    // Create destination file
-    let mut file = std::fs::File::create(file_path)?;
-    let inner = match content.bytes() {
-        Ok(i) => i,
-        Err(err) => panic!("There was an error reading content, {err}"),
+/// Panic unless the downloaded proto has the checksum recorded for it, showing how it differs from
+/// the vendored one.
+fn verify_checksum(
+    definition: &ProtoDefinition,
+    file: &ProtoFile,
+    vendored_path: &str,
+    content: &str,
+) {
+    let checksum = format!("{:x}", Sha256::digest(content.as_bytes()));
+    if file.checksum == checksum {
+        return;
+    }
+
+    let vendored = std::fs::read_to_string(vendored_path).unwrap_or_default();
+    let diff = TextDiff::from_lines(vendored.as_str(), content)
+        .unified_diff()
+        .header(vendored_path, "downloaded")
+        .to_string();
+    let reason = if file.checksum.is_empty() {
+        format!("has checksum {checksum}, but there is none recorded for it")
+    } else {
+        format!("has checksum {checksum} instead of {}", file.checksum)
     };
-    let mut content = Cursor::new(inner);
-    std::io::copy(&mut content, &mut file)?;
-    Ok(())
+    panic!(
+        "{} at {} {reason}, compared to the vendored one:\n{diff}",
+        file.path, definition.commit
+    );
+}
+
+fn save_content_to_file(vendored_path: &str, content: &str) -> Result<()> {
+    let path = env::current_dir()?.join(vendored_path);
+    // Create folder if missing
+    if let Some(folder) = path.parent() {
+        std::fs::create_dir_all(folder)?;
+    }
+    std::fs::write(path, content)
 }
 
 fn download_file(client: reqwest::blocking::Client, file_url: Url) -> reqwest::blocking::Response {
@@ -116,10 +233,8 @@ fn get_file_info(client: &reqwest::blocking::Client, url: Url) -> serde_json::Va
     }
 }
 
-fn build_github_url_to_download() -> Url {
-    let github_url = format!(
-        "{DCL_PROTOCOL_REPO_URL}{FRIENDSHIP_PROTO_PATH}?ref={FRIENDSHIPS_PROTOCOL_VERSION}"
-    );
+fn build_github_url_to_download(path: &str, commit: &str) -> Url {
+    let github_url = format!("{DCL_PROTOCOL_REPO_URL}/{path}?ref={commit}");
 
     match Url::parse(&github_url) {
         Ok(it) => it,
@@ -0,0 +1,468 @@
+use std::{fmt, io, io::Write, sync::Arc};
+
+use serde::Serialize;
+
+use crate::{
+    config::Environment,
+    credentials::{AuthUser, Users},
+    error::SocialClientError,
+    event_stream::FriendshipEventStream,
+    friendship_event_payload, friendship_event_response,
+    friendship_procedures::Flow,
+    friendship_state::FriendshipAction,
+    reconnect::ReconnectingClient,
+    update_friendship_response, AcceptPayload, CancelPayload, DeletePayload,
+    FriendshipEventResponse, RejectPayload, RequestEvents, RequestPayload, RequestResponse,
+    Requests, UpdateFriendshipResponse, User,
+};
+
+pub const USAGE: &str = "Usage: social-cli [--env <name>] [--host <url>] [--config <path>] [--credentials <path>] [--as <user>] [--output <text|json>] <command>
+
+Users are given by their label in the credentials or by their address, the acting user is the
+first one of the credentials unless given with --as. With --output json, the results are the
+messages of the social service as JSON, one per line for friends, mutual friends and events.
+
+Commands:
+  friends                            List the friends of the acting user
+  requests                           List the pending friendship requests of the acting user
+  mutuals <user>                     List the mutual friends with a user
+  request <user> [--message <text>]  Send a friendship request to a user
+  accept <user>                      Accept the friendship request received from a user
+  reject <user>                      Reject the friendship request received from a user
+  cancel <user>                      Cancel the friendship request sent to a user
+  delete <user>                      Delete the friendship with a user
+  listen                             Print the friendship events until interrupted
+  run-flow <flow> <user>             Run flow1 to flow4 with a user of the credentials";
+
+const COMMANDS: [&str; 10] = [
+    "friends", "requests", "mutuals", "request", "accept", "reject", "cancel", "delete", "listen",
+    "run-flow",
+];
+
+/// A `social-cli` command, acting on behalf of a user of the credentials.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    Friends,
+    Requests,
+    Mutuals(String),
+    /// Send a friendship event update to the target, the message is only sent with requests.
+    Update {
+        action: FriendshipAction,
+        target: String,
+        message: Option<String>,
+    },
+    Listen,
+    /// Run a flow between the acting user and another user of the credentials.
+    RunFlow {
+        flow: Flow,
+        target: String,
+    },
+}
+
+/// How `social-cli` writes the results.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Lines meant to be read.
+    #[default]
+    Text,
+    /// The messages of the social service as JSON, one per line for the streamed ones (friends,
+    /// mutual friends and events), with the field names of the proto.
+    Json,
+}
+
+impl OutputFormat {
+    pub fn parse(format: &str) -> Option<Self> {
+        match format {
+            "text" => Some(OutputFormat::Text),
+            "json" => Some(OutputFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// The command-line arguments of `social-cli`, once the environment and credentials flags are
+/// removed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CliArgs {
+    /// The label or address of the acting user, the first user if `None`.
+    pub user: Option<String>,
+    pub command: Command,
+    pub output: OutputFormat,
+}
+
+/// Errors returned by the `social-cli` commands.
+#[derive(Debug)]
+pub enum CliError {
+    /// The arguments are invalid, with the reason.
+    Usage(String),
+    /// The user isn't in the credentials, which have users with the given labels.
+    UnknownUser {
+        user: String,
+        labels: Vec<String>,
+    },
+    Client(SocialClientError),
+    Output(io::Error),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Usage(reason) => write!(f, "{reason}\n{USAGE}"),
+            CliError::UnknownUser { user, labels } => write!(
+                f,
+                "There is no user {user} in the credentials, the users are {}",
+                labels.join(", ")
+            ),
+            CliError::Client(err) => write!(f, "{err}"),
+            CliError::Output(err) => write!(f, "Failed to write the output: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+impl From<SocialClientError> for CliError {
+    fn from(err: SocialClientError) -> Self {
+        CliError::Client(err)
+    }
+}
+
+impl From<io::Error> for CliError {
+    fn from(err: io::Error) -> Self {
+        CliError::Output(err)
+    }
+}
+
+impl CliArgs {
+    /// Parse the `--as <user>` and `--output <text|json>` flags and the command.
+    pub fn parse(args: &[String]) -> Result<Self, CliError> {
+        let mut user = None;
+        let mut message = None;
+        let mut output = OutputFormat::default();
+        let mut positional = Vec::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--as" => user = Some(flag_value(&mut args, "--as")?),
+                "--output" => {
+                    let format = flag_value(&mut args, "--output")?;
+                    output = OutputFormat::parse(&format).ok_or_else(|| {
+                        CliError::Usage(format!("Unknown output format {format}"))
+                    })?;
+                }
+                "--message" => message = Some(flag_value(&mut args, "--message")?),
+                flag if flag.starts_with("--") => {
+                    return Err(CliError::Usage(format!("Unexpected argument {flag}")))
+                }
+                _ => positional.push(arg.as_str()),
+            }
+        }
+
+        let update = positional.first().and_then(|command| parse_action(command));
+        let command = match (update, &positional[..]) {
+            (Some(action), [_, target]) => Command::Update {
+                action,
+                target: target.to_string(),
+                message: match action {
+                    FriendshipAction::Request => message.take(),
+                    _ => None,
+                },
+            },
+            (_, ["friends"]) => Command::Friends,
+            (_, ["requests"]) => Command::Requests,
+            (_, ["mutuals", target]) => Command::Mutuals(target.to_string()),
+            (_, ["listen"]) => Command::Listen,
+            (_, ["run-flow", flow, target]) => match Flow::from_str(flow) {
+                Some(flow @ (Flow::Flow1 | Flow::Flow2 | Flow::Flow3 | Flow::Flow4)) => {
+                    Command::RunFlow {
+                        flow,
+                        target: target.to_string(),
+                    }
+                }
+                _ => return Err(CliError::Usage(format!("Unknown flow {flow}"))),
+            },
+            (_, []) => return Err(CliError::Usage("No command given".to_string())),
+            (_, [command, ..]) if COMMANDS.contains(command) => {
+                return Err(CliError::Usage(format!(
+                    "Unexpected arguments for {command}"
+                )))
+            }
+            (_, [command, ..]) => {
+                return Err(CliError::Usage(format!("Unknown command {command}")))
+            }
+        };
+        if message.is_some() {
+            return Err(CliError::Usage(
+                "--message is only valid for request".to_string(),
+            ));
+        }
+        Ok(Self {
+            user,
+            command,
+            output,
+        })
+    }
+
+    /// The users of the credentials the command acts as, the acting user and the other one of a
+    /// flow, whose tokens are the ones to check before running it.
+    pub fn users<'a>(&self, users: &'a Users) -> Result<Vec<&'a AuthUser>, CliError> {
+        let mut indexes = vec![self.acting_user(users)?];
+        if let Command::RunFlow { target, .. } = &self.command {
+            indexes.push(loaded_user(users, target)?);
+        }
+        Ok(indexes
+            .into_iter()
+            .map(|index| &users.users()[index])
+            .collect())
+    }
+
+    /// The position of the acting user in the credentials.
+    fn acting_user(&self, users: &Users) -> Result<usize, CliError> {
+        let index = match &self.user {
+            Some(user) => loaded_user(users, user)?,
+            None => 0,
+        };
+        if index >= users.users().len() {
+            return Err(CliError::Usage(
+                "There are no users in the credentials".to_string(),
+            ));
+        }
+        Ok(index)
+    }
+
+    /// Run the command with the given credentials, each user connecting to its host of the
+    /// environment, and write its result.
+    pub async fn run(
+        &self,
+        users: &Users,
+        environment: &Environment,
+        out: &mut impl Write,
+    ) -> Result<(), CliError> {
+        let index = self.acting_user(users)?;
+        let user = &users.users()[index];
+        let hosts = |index: usize| environment.user_hosts(&users.labels()[index], index);
+        let client = ReconnectingClient::for_hosts(hosts(index), user.clone());
+
+        match &self.command {
+            Command::Friends => {
+                let friends = client
+                    .call(|client| async move { client.get_friends().await })
+                    .await?;
+                write_users(out, self.output, &friends)?;
+            }
+            Command::Requests => {
+                let events = client
+                    .call(|client| async move { client.get_request_events().await })
+                    .await?;
+                write_request_events(out, self.output, &events)?;
+            }
+            Command::Mutuals(target) => {
+                let address = target_address(users, target)?;
+                let mutuals = client
+                    .call(|client| {
+                        let address = address.clone();
+                        async move { client.get_mutual_friends(&address).await }
+                    })
+                    .await?;
+                write_users(out, self.output, &mutuals)?;
+            }
+            Command::Update {
+                action,
+                target,
+                message,
+            } => {
+                let body = event_body(*action, &target_address(users, target)?, message.clone());
+                let response = client
+                    .call(|client| {
+                        let body = body.clone();
+                        async move { client.update_friendship_event(body).await }
+                    })
+                    .await?;
+                write_update(out, self.output, &response)?;
+            }
+            Command::Listen => {
+                let mut events = FriendshipEventStream::new(Arc::new(client));
+                loop {
+                    let event = events.next().await?;
+                    write_event(out, self.output, &event)?;
+                    out.flush()?;
+                }
+            }
+            Command::RunFlow { flow, target } => {
+                let other_index = loaded_user(users, target)?;
+                let other = &users.users()[other_index];
+                let other_client = ReconnectingClient::for_hosts(hosts(other_index), other.clone());
+                let responses = run_flow(flow, &client, user, &other_client, other).await?;
+                for response in &responses {
+                    write_update(out, self.output, response)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+async fn run_flow(
+    flow: &Flow,
+    client: &ReconnectingClient,
+    user: &AuthUser,
+    other_client: &ReconnectingClient,
+    other: &AuthUser,
+) -> Result<Vec<UpdateFriendshipResponse>, SocialClientError> {
+    let (module, other_module) = (client.client().await?, other_client.client().await?);
+    flow.execute_flow(
+        module.module(),
+        other_module.module(),
+        user.clone(),
+        other.clone(),
+    )
+    .await
+}
+
+fn parse_action(action: &str) -> Option<FriendshipAction> {
+    match action {
+        "request" => Some(FriendshipAction::Request),
+        "accept" => Some(FriendshipAction::Accept),
+        "reject" => Some(FriendshipAction::Reject),
+        "cancel" => Some(FriendshipAction::Cancel),
+        "delete" => Some(FriendshipAction::Delete),
+        _ => None,
+    }
+}
+
+fn event_body(
+    action: FriendshipAction,
+    address: &str,
+    message: Option<String>,
+) -> friendship_event_payload::Body {
+    let user = Some(User {
+        address: address.to_string(),
+    });
+    match action {
+        FriendshipAction::Request => {
+            friendship_event_payload::Body::Request(RequestPayload { user, message })
+        }
+        FriendshipAction::Accept => friendship_event_payload::Body::Accept(AcceptPayload { user }),
+        FriendshipAction::Reject => friendship_event_payload::Body::Reject(RejectPayload { user }),
+        FriendshipAction::Cancel => friendship_event_payload::Body::Cancel(CancelPayload { user }),
+        FriendshipAction::Delete => friendship_event_payload::Body::Delete(DeletePayload { user }),
+    }
+}
+
+fn flag_value<'a>(
+    args: &mut impl Iterator<Item = &'a String>,
+    flag: &str,
+) -> Result<String, CliError> {
+    args.next()
+        .cloned()
+        .ok_or_else(|| CliError::Usage(format!("Missing value for {flag}")))
+}
+
+/// The position of the given user of the credentials.
+fn loaded_user(users: &Users, user: &str) -> Result<usize, CliError> {
+    users.position(user).ok_or_else(|| CliError::UnknownUser {
+        user: user.to_string(),
+        labels: users.labels().to_vec(),
+    })
+}
+
+/// The address of the given user, which doesn't need to be in the credentials if it's an address.
+fn target_address(users: &Users, target: &str) -> Result<String, CliError> {
+    match users.position(target) {
+        Some(index) => Ok(users.users()[index].address.clone()),
+        None if target.starts_with("0x") => Ok(target.to_string()),
+        None => Err(CliError::UnknownUser {
+            user: target.to_string(),
+            labels: users.labels().to_vec(),
+        }),
+    }
+}
+
+/// With [`OutputFormat::Json`], every user is a JSON line like `{"address":"0x..."}`.
+fn write_users(out: &mut impl Write, format: OutputFormat, users: &[User]) -> io::Result<()> {
+    for user in users {
+        match format {
+            OutputFormat::Text => writeln!(out, "{}", user.address)?,
+            OutputFormat::Json => write_json(out, user)?,
+        }
+    }
+    Ok(())
+}
+
+fn write_request_events(
+    out: &mut impl Write,
+    format: OutputFormat,
+    events: &RequestEvents,
+) -> io::Result<()> {
+    match format {
+        OutputFormat::Text => {
+            write_requests(out, "Incoming", events.incoming.as_ref())?;
+            write_requests(out, "Outgoing", events.outgoing.as_ref())
+        }
+        OutputFormat::Json => write_json(out, events),
+    }
+}
+
+fn write_requests(
+    out: &mut impl Write,
+    which: &str,
+    requests: Option<&Requests>,
+) -> io::Result<()> {
+    let items: &[RequestResponse] = requests.map_or(&[], |requests| &requests.items);
+    writeln!(out, "{which} requests: {}", items.len())?;
+    for request in items {
+        let address = request
+            .user
+            .as_ref()
+            .map_or("", |user| user.address.as_str());
+        match &request.message {
+            Some(message) => writeln!(out, "  {address} at {}: {message}", request.created_at)?,
+            None => writeln!(out, "  {address} at {}", request.created_at)?,
+        }
+    }
+    Ok(())
+}
+
+fn write_update(
+    out: &mut impl Write,
+    format: OutputFormat,
+    response: &UpdateFriendshipResponse,
+) -> io::Result<()> {
+    match &response.response {
+        Some(update_friendship_response::Response::Event(event)) => write_event(out, format, event),
+        _ => Ok(()),
+    }
+}
+
+fn write_event(
+    out: &mut impl Write,
+    format: OutputFormat,
+    event: &FriendshipEventResponse,
+) -> io::Result<()> {
+    match format {
+        OutputFormat::Text => writeln!(out, "{}", describe_event(event)),
+        OutputFormat::Json => write_json(out, event),
+    }
+}
+
+/// Write the value on its own line, so streamed values are newline-delimited JSON.
+fn write_json(out: &mut impl Write, value: &impl Serialize) -> io::Result<()> {
+    serde_json::to_writer(&mut *out, value)?;
+    writeln!(out)
+}
+
+/// Like `request 0x...: message`.
+fn describe_event(event: &FriendshipEventResponse) -> String {
+    let Some(body) = &event.body else {
+        return "empty event".to_string();
+    };
+    let (action, address) = FriendshipAction::from_event(body);
+    let address = address.unwrap_or_default();
+    match body {
+        friendship_event_response::Body::Request(RequestResponse {
+            message: Some(message),
+            ..
+        }) => format!("{action} {address}: {message}"),
+        _ => format!("{action} {address}"),
+    }
+}
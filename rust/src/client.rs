@@ -0,0 +1,215 @@
+use std::time::Duration;
+
+use dcl_rpc::{
+    client::RpcClient,
+    transports::{
+        self,
+        web_sockets::{
+            tungstenite::{TungsteniteWebSocket, WebSocketClient},
+            WebSocketTransport,
+        },
+    },
+};
+
+use crate::{
+    credentials::AuthUser,
+    error::SocialClientError,
+    friendship_event_payload,
+    friendship_procedures::{
+        auth_payload, get_friends, get_mutual_friends, get_request_events, update_friendship_event,
+    },
+    friendship_state::{
+        get_friendship_state, validate_transition, wait_for_friendship_state, FriendshipAction,
+        FriendshipState,
+    },
+    AcceptPayload, CancelPayload, DeletePayload, FriendshipsServiceClient,
+    FriendshipsServiceClientDefinition, RejectPayload, RequestEvents, RequestPayload,
+    ServerStreamResponse, SubscribeFriendshipEventsUpdatesResponse, UpdateFriendshipResponse, User,
+};
+
+pub type Transport = WebSocketTransport<TungsteniteWebSocket, ()>;
+
+pub(crate) const FRIENDSHIPS_PORT: &str = "friendships";
+const FRIENDSHIPS_SERVICE: &str = "FriendshipsService";
+
+/// A connection to the social service bound to a single user.
+///
+/// Owns the RPC client, the `friendships` port and the loaded `FriendshipsService` module,
+/// and authenticates every procedure with the token of the bound user.
+pub struct SocialClient<T: transports::Transport + 'static = Transport> {
+    user: AuthUser,
+    module: FriendshipsServiceClient<T>,
+    // The RPC client stops processing responses when dropped, so it has to live as long as the module.
+    _rpc_client: RpcClient<T>,
+}
+
+impl SocialClient {
+    /// Connect to the given host and load the `FriendshipsService` module for the given user.
+    pub async fn connect(host: &str, user: AuthUser) -> Result<Self, SocialClientError> {
+        let client_connection = WebSocketClient::connect(host)
+            .await
+            .map_err(|err| SocialClientError::Connection(format!("{err:?}")))?;
+        let client_transport = WebSocketTransport::new(client_connection);
+        Self::from_transport(client_transport, user).await
+    }
+}
+
+impl<T: transports::Transport + 'static> SocialClient<T> {
+    /// Load the `FriendshipsService` module for the given user over an already connected
+    /// transport, e.g. a `MemoryTransport` to a [`MockSocialServer`](crate::mock_server::MockSocialServer).
+    pub async fn from_transport(transport: T, user: AuthUser) -> Result<Self, SocialClientError> {
+        let mut rpc_client = RpcClient::new(transport)
+            .await
+            .map_err(|err| SocialClientError::Connection(format!("{err:?}")))?;
+
+        let port = rpc_client
+            .create_port(FRIENDSHIPS_PORT)
+            .await
+            .map_err(SocialClientError::PortCreation)?;
+
+        let module = port
+            .load_module::<FriendshipsServiceClient<T>>(FRIENDSHIPS_SERVICE)
+            .await
+            .map_err(SocialClientError::ModuleLoading)?;
+
+        Ok(Self {
+            user,
+            module,
+            _rpc_client: rpc_client,
+        })
+    }
+
+    /// The user this client is authenticated as.
+    pub fn user(&self) -> &AuthUser {
+        &self.user
+    }
+
+    /// The underlying module client, e.g. for executing a [`Flow`](crate::friendship_procedures::Flow).
+    pub fn module(&self) -> &FriendshipsServiceClient<T> {
+        &self.module
+    }
+
+    /// Get the friends of the bound user.
+    pub async fn get_friends(&self) -> Result<Vec<User>, SocialClientError> {
+        get_friends(&self.module, &self.user).await
+    }
+
+    /// Get the mutual friends between the bound user and the given address.
+    pub async fn get_mutual_friends(&self, address: &str) -> Result<Vec<User>, SocialClientError> {
+        get_mutual_friends(&self.module, &self.user, address).await
+    }
+
+    /// Get the incoming and outgoing friendship requests of the bound user.
+    pub async fn get_request_events(&self) -> Result<RequestEvents, SocialClientError> {
+        get_request_events(&self.module, &self.user).await
+    }
+
+    /// Get the friendship between the bound user and the given address.
+    pub async fn friendship_state(
+        &self,
+        address: &str,
+    ) -> Result<FriendshipState, SocialClientError> {
+        get_friendship_state(&self.module, &self.user, address).await
+    }
+
+    /// Wait until the friendship between the bound user and the given address is the expected one,
+    /// failing if it isn't within the given timeout.
+    pub async fn wait_for_friendship_state(
+        &self,
+        address: &str,
+        expected: FriendshipState,
+        timeout: Duration,
+    ) -> Result<(), SocialClientError> {
+        wait_for_friendship_state(&self.module, &self.user, address, expected, timeout).await
+    }
+
+    /// Send a friendship event update on behalf of the bound user.
+    ///
+    /// The update is checked against the current friendship first, failing with
+    /// [`SocialClientError::InvalidTransition`] without sending it if it isn't allowed.
+    /// The update may not be observable right away, see [`Self::wait_for_friendship_state`].
+    pub async fn update_friendship_event(
+        &self,
+        body: friendship_event_payload::Body,
+    ) -> Result<UpdateFriendshipResponse, SocialClientError> {
+        if let (action, Some(address)) = FriendshipAction::from_body(&body) {
+            validate_transition(&self.module, &self.user, address, action).await?;
+        }
+        update_friendship_event(&self.module, &self.user.token, body).await
+    }
+
+    /// Send a friendship request to the given address.
+    pub async fn request(
+        &self,
+        address: &str,
+        message: Option<String>,
+    ) -> Result<UpdateFriendshipResponse, SocialClientError> {
+        self.update_friendship_event(friendship_event_payload::Body::Request(RequestPayload {
+            user: Some(user(address)),
+            message,
+        }))
+        .await
+    }
+
+    /// Accept the friendship request received from the given address.
+    pub async fn accept(
+        &self,
+        address: &str,
+    ) -> Result<UpdateFriendshipResponse, SocialClientError> {
+        self.update_friendship_event(friendship_event_payload::Body::Accept(AcceptPayload {
+            user: Some(user(address)),
+        }))
+        .await
+    }
+
+    /// Reject the friendship request received from the given address.
+    pub async fn reject(
+        &self,
+        address: &str,
+    ) -> Result<UpdateFriendshipResponse, SocialClientError> {
+        self.update_friendship_event(friendship_event_payload::Body::Reject(RejectPayload {
+            user: Some(user(address)),
+        }))
+        .await
+    }
+
+    /// Cancel the friendship request sent to the given address.
+    pub async fn cancel(
+        &self,
+        address: &str,
+    ) -> Result<UpdateFriendshipResponse, SocialClientError> {
+        self.update_friendship_event(friendship_event_payload::Body::Cancel(CancelPayload {
+            user: Some(user(address)),
+        }))
+        .await
+    }
+
+    /// Delete the friendship with the given address.
+    pub async fn delete(
+        &self,
+        address: &str,
+    ) -> Result<UpdateFriendshipResponse, SocialClientError> {
+        self.update_friendship_event(friendship_event_payload::Body::Delete(DeletePayload {
+            user: Some(user(address)),
+        }))
+        .await
+    }
+
+    /// Subscribe to the friendship events updates of the bound user.
+    pub async fn subscribe_friendship_events_updates(
+        &self,
+    ) -> Result<ServerStreamResponse<SubscribeFriendshipEventsUpdatesResponse>, SocialClientError>
+    {
+        let updates = self
+            .module
+            .subscribe_friendship_events_updates(auth_payload(&self.user.token))
+            .await?;
+        Ok(updates)
+    }
+}
+
+fn user(address: &str) -> User {
+    User {
+        address: address.to_string(),
+    }
+}
@@ -1,74 +1,114 @@
-use dcl_rpc::transports::web_sockets::tungstenite::WebSocketClient;
-use dcl_rpc::{
-    client::RpcClient,
-    transports::web_sockets::{tungstenite::TungsteniteWebSocket, WebSocketTransport},
-};
-use social_client::friendship_procedures::Flow;
-use social_client::{credentials::load_users, FriendshipsServiceClient};
+use std::{env, process::ExitCode};
 
-type Transport = WebSocketTransport<TungsteniteWebSocket, ()>;
+use social_client::config::load_environment;
+use social_client::credentials::load_users_from_args;
+use social_client::error::SocialClientError;
+use social_client::friendship_state::{FriendshipState, CONVERGENCE_TIMEOUT};
+use social_client::reconnect::ReconnectingClient;
+use social_client::token::check_tokens;
 
-const RECONNECT_DELAY: u64 = 10; // seconds
+/// How long to wait for Client B to act on the request.
+const WAIT_FOR_CLIENT_B: tokio::time::Duration = tokio::time::Duration::from_secs(60);
 
 #[tokio::main]
-async fn main() {
-    // Auth Users
-    let [user_a, user_b, _] = load_users().await;
-
-    let host = "ws://127.0.0.1:8085";
-
-    loop {
-        match WebSocketClient::connect(host).await {
-            Ok(client_connection) => {
-                let client_transport = WebSocketTransport::new(client_connection.clone());
-
-                let mut client = RpcClient::new(client_transport).await.unwrap();
-
-                let port = client.create_port("friendships").await.unwrap();
-
-                let module = port
-                    .load_module::<FriendshipsServiceClient<Transport>>("FriendshipsService")
-                    .await
-                    .unwrap();
-
-                println!("Running Client A...");
-
-                println!("Sending request to Client B...");
-                let request = Flow::Request;
-                request
-                    .execute_event(&module, user_a.clone(), user_b.clone())
-                    .await;
-
-                println!("Waiting for Client B to accept the request...");
-                tokio::time::sleep(tokio::time::Duration::from_secs(15)).await;
+async fn main() -> ExitCode {
+    // Hosts
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let environment = match load_environment(&mut args) {
+        Ok(environment) => environment,
+        Err(err) => {
+            println!("Invalid configuration: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
 
-                println!("Deleting friendship...");
-                let delete = Flow::Delete;
-                delete
-                    .execute_event(&module, user_a.clone(), user_b.clone())
-                    .await;
+    // Auth Users
+    let ([user_a, user_b], users) = match load_users_from_args(&mut args, 2)
+        .await
+        .and_then(|users| Ok((users.first()?, users)))
+    {
+        Ok(users) => users,
+        Err(err) => {
+            println!("Failed to load credentials: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    match check_tokens([&user_a]) {
+        Ok(warnings) => {
+            for warning in warnings {
+                println!("Warning: {warning}");
+            }
+        }
+        Err(err) => {
+            println!("Refusing to start: {err}");
+            return ExitCode::FAILURE;
+        }
+    }
 
-                println!("Sending request to Client B...");
-                let request = Flow::Request;
-                request
-                    .execute_event(&module, user_a.clone(), user_b.clone())
-                    .await;
+    let hosts = environment.user_hosts(&users.labels()[0], 0);
 
-                println!("Waiting before canceling the request...");
-                tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+    let client = ReconnectingClient::for_hosts(hosts, user_a)
+        .on_state_change(|state| println!("> Connection > {state:?}"));
 
-                println!("Canceling request to Client B...");
-                let cancel = Flow::Cancel;
-                cancel
-                    .execute_event(&module, user_a.clone(), user_b.clone())
-                    .await;
+    println!("Running Client A...");
 
-                break;
-            }
-            _ => {
-                println!("Failed to connect, retrying in {RECONNECT_DELAY} seconds...");
-                tokio::time::sleep(tokio::time::Duration::from_secs(RECONNECT_DELAY)).await;
-            }
-        }
+    if let Err(err) = run(&client, &user_b.address).await {
+        println!("Client A failed: {err}");
+        return ExitCode::FAILURE;
     }
+    ExitCode::SUCCESS
+}
+
+async fn run(client: &ReconnectingClient, address_b: &str) -> Result<(), SocialClientError> {
+    println!("Sending request to Client B...");
+    let response = client
+        .call(|client| async move {
+            client
+                .request(address_b, Some("A message".to_string()))
+                .await
+        })
+        .await?;
+    println!("> Server Unary > Response > UpdateFrienshipResponse:: {response:?}");
+
+    println!("Waiting for Client B to accept the request...");
+    client
+        .wait_for_friendship_state(address_b, FriendshipState::Friends, WAIT_FOR_CLIENT_B)
+        .await?;
+
+    println!("Deleting friendship...");
+    let response = client
+        .call(|client| async move { client.delete(address_b).await })
+        .await?;
+    println!("> Server Unary > Response > UpdateFrienshipResponse:: {response:?}");
+
+    client
+        .wait_for_friendship_state(address_b, FriendshipState::None, CONVERGENCE_TIMEOUT)
+        .await?;
+
+    println!("Sending request to Client B...");
+    let response = client
+        .call(|client| async move {
+            client
+                .request(address_b, Some("A message".to_string()))
+                .await
+        })
+        .await?;
+    println!("> Server Unary > Response > UpdateFrienshipResponse:: {response:?}");
+
+    println!("Waiting for the request to be registered before canceling it...");
+    client
+        .wait_for_friendship_state(
+            address_b,
+            FriendshipState::PendingOutgoing,
+            CONVERGENCE_TIMEOUT,
+        )
+        .await?;
+
+    println!("Canceling request to Client B...");
+    let response = client
+        .call(|client| async move { client.cancel(address_b).await })
+        .await?;
+    println!("> Server Unary > Response > UpdateFrienshipResponse:: {response:?}");
+
+    Ok(())
 }
@@ -1,56 +1,96 @@
-use dcl_rpc::transports::web_sockets::tungstenite::WebSocketClient;
-use dcl_rpc::{
-    client::RpcClient,
-    transports::web_sockets::{tungstenite::TungsteniteWebSocket, WebSocketTransport},
-};
-use social_client::friendship_procedures::Flow;
-use social_client::{credentials::load_users, FriendshipsServiceClient};
+use std::{env, process::ExitCode};
 
-type Transport = WebSocketTransport<TungsteniteWebSocket, ()>;
+use social_client::config::load_environment;
+use social_client::credentials::load_users_from_args;
+use social_client::error::SocialClientError;
+use social_client::friendship_state::FriendshipState;
+use social_client::reconnect::ReconnectingClient;
+use social_client::token::check_tokens;
 
-const RECONNECT_DELAY: u64 = 10; // seconds
+/// How long to wait for Client A to act on the friendship.
+const WAIT_FOR_CLIENT_A: tokio::time::Duration = tokio::time::Duration::from_secs(60);
 
 #[tokio::main]
-async fn main() {
-    // Auth Users
-    let [user_a, user_b, _] = load_users().await;
+async fn main() -> ExitCode {
+    // Hosts
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let environment = match load_environment(&mut args) {
+        Ok(environment) => environment,
+        Err(err) => {
+            println!("Invalid configuration: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
 
-    let host = "ws://127.0.0.1:8085";
+    // Auth Users
+    let ([user_a, user_b], users) = match load_users_from_args(&mut args, 2)
+        .await
+        .and_then(|users| Ok((users.first()?, users)))
+    {
+        Ok(users) => users,
+        Err(err) => {
+            println!("Failed to load credentials: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    match check_tokens([&user_b]) {
+        Ok(warnings) => {
+            for warning in warnings {
+                println!("Warning: {warning}");
+            }
+        }
+        Err(err) => {
+            println!("Refusing to start: {err}");
+            return ExitCode::FAILURE;
+        }
+    }
 
-    loop {
-        match WebSocketClient::connect(host).await {
-            Ok(client_connection) => {
-                let client_transport = WebSocketTransport::new(client_connection.clone());
+    let hosts = environment.user_hosts(&users.labels()[1], 1);
 
-                let mut client = RpcClient::new(client_transport).await.unwrap();
+    let client = ReconnectingClient::for_hosts(hosts, user_b)
+        .on_state_change(|state| println!("> Connection > {state:?}"));
 
-                let port = client.create_port("friendships").await.unwrap();
+    println!("Running Client B...");
 
-                let module = port
-                    .load_module::<FriendshipsServiceClient<Transport>>("FriendshipsService")
-                    .await
-                    .unwrap();
+    if let Err(err) = run(&client, &user_a.address).await {
+        println!("Client B failed: {err}");
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
 
-                println!("Running Client B...");
+async fn run(client: &ReconnectingClient, address_a: &str) -> Result<(), SocialClientError> {
+    println!("Waiting for the request from Client A...");
+    client
+        .wait_for_friendship_state(
+            address_a,
+            FriendshipState::PendingIncoming,
+            WAIT_FOR_CLIENT_A,
+        )
+        .await?;
 
-                println!("Accepting request from Client A...");
-                let accept = Flow::Accept;
-                accept
-                    .execute_event(&module, user_a.clone(), user_b.clone())
-                    .await;
+    println!("Accepting request from Client A...");
+    let response = client
+        .call(|client| async move { client.accept(address_a).await })
+        .await?;
+    println!("> Server Unary > Response > UpdateFrienshipResponse:: {response:?}");
 
-                println!("Waiting for Client A to delete the friendship...");
-                tokio::time::sleep(tokio::time::Duration::from_secs(20)).await;
+    println!("Waiting for Client A to delete the friendship...");
+    client
+        .wait_for_friendship_state(address_a, FriendshipState::None, WAIT_FOR_CLIENT_A)
+        .await?;
 
-                println!("Waiting for Client A to send a friendship request and cancel it...");
-                tokio::time::sleep(tokio::time::Duration::from_secs(10)).await;
+    println!("Waiting for Client A to send a friendship request and cancel it...");
+    client
+        .wait_for_friendship_state(
+            address_a,
+            FriendshipState::PendingIncoming,
+            WAIT_FOR_CLIENT_A,
+        )
+        .await?;
+    client
+        .wait_for_friendship_state(address_a, FriendshipState::None, WAIT_FOR_CLIENT_A)
+        .await?;
 
-                break;
-            }
-            _ => {
-                println!("Failed to connect, retrying in {RECONNECT_DELAY} seconds...");
-                tokio::time::sleep(tokio::time::Duration::from_secs(RECONNECT_DELAY)).await;
-            }
-        }
-    }
+    Ok(())
 }
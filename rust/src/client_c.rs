@@ -1,105 +1,135 @@
-use dcl_rpc::transports::web_sockets::tungstenite::WebSocketClient;
-use dcl_rpc::{
-    client::RpcClient,
-    transports::web_sockets::{tungstenite::TungsteniteWebSocket, WebSocketTransport},
-};
-use social_client::{FriendshipsServiceClientDefinition, MutualFriendsPayload, User, Payload};
-use social_client::credentials::AuthUser;
-use social_client::friendship_procedures::Flow;
-use social_client::{credentials::load_users, FriendshipsServiceClient};
+use std::{env, process::ExitCode};
 
-type Transport = WebSocketTransport<TungsteniteWebSocket, ()>;
-
-const RECONNECT_DELAY: u64 = 10; // seconds
+use social_client::config::{load_environment, Environment};
+use social_client::credentials::{load_users_from_args, short_address, AuthUser};
+use social_client::error::SocialClientError;
+use social_client::friendship_state::{FriendshipState, CONVERGENCE_TIMEOUT};
+use social_client::reconnect::ReconnectingClient;
+use social_client::token::check_tokens;
 
 #[tokio::main]
-async fn main() {
-    // Auth Users
-    let [user_a, user_b, user_c] = load_users().await;
-
-    let host = "ws://127.0.0.1:8085";
-
-    match WebSocketClient::connect(host).await {
-        Ok(client_connection) => {
-            let client_transport = WebSocketTransport::new(client_connection.clone());
-
-            let mut client = RpcClient::new(client_transport).await.unwrap();
-
-            let port = client.create_port("friendships").await.unwrap();
-
-            let module = port
-                .load_module::<FriendshipsServiceClient<Transport>>("FriendshipsService")
-                .await
-                .unwrap();
-
-            println!("C -> B: send request");
-            let request = Flow::Request;
-            request
-                .execute_event(&module, user_c.clone(), user_b.clone())
-                .await;
-
-            println!("Waiting for Matrix to update the status...");
-            tokio::time::sleep(tokio::time::Duration::from_secs(20)).await;
-
-            println!("B -> C: accept request");
-            let accept = Flow::Accept;
-            accept
-                .execute_event(&module, user_c.clone(), user_b.clone())
-                .await;
-
-            println!("A -> C: send request");
-            let request = Flow::Request;
-            request
-                .execute_event(&module, user_a.clone(), user_c.clone())
-                .await;
-
-            println!("Waiting for Matrix to update the status...");
-            tokio::time::sleep(tokio::time::Duration::from_secs(20)).await;
-            
-            println!("C -> A: accept request");
-            let accept = Flow::Accept;
-            accept
-                .execute_event(&module, user_a.clone(), user_c.clone())
-                .await;
-
-            println!("Waiting for Matrix to update the status...");
-            tokio::time::sleep(tokio::time::Duration::from_secs(20)).await;
-
-            get_mutual_friends(&module, &user_a, &user_c).await;
-        }
-        _ => {
-            println!("Failed to connect, retrying in {RECONNECT_DELAY} seconds...");
-            tokio::time::sleep(tokio::time::Duration::from_secs(RECONNECT_DELAY)).await;
+async fn main() -> ExitCode {
+    // Hosts
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let environment = match load_environment(&mut args) {
+        Ok(environment) => environment,
+        Err(err) => {
+            println!("Invalid configuration: {err}");
+            return ExitCode::FAILURE;
         }
-    }
-    
-}
-
+    };
 
-/// Get and print the friends of the given user using the given module client.
-pub async fn get_mutual_friends(module: &FriendshipsServiceClient<Transport>, user: &AuthUser, other_user: &AuthUser) {
-    let friends_response = module
-        .get_mutual_friends(MutualFriendsPayload {
-            user: Some(User{address: other_user.clone().address}),
-            auth_token: Some(Payload{synapse_token: Some(user.clone().token)}),
-        })
-        .await;
-    match friends_response {
-        Ok(mut friends_response) => {
-            println!(
-                "> Server Streams > Response > GetMutualFriendsResponse for {:?}",
-                &user.clone().address[user.address.len() - 4..]
-            );
-            while let Some(friend) = friends_response.next().await {
-                println!(
-                    "> Server Streams > Response > GetMutualFriendsResponse for {:?}: {:?}",
-                    &user.clone().address[user.address.len() - 4..],
-                    friend.response
-                )
+    // Auth Users
+    let ([user_a, user_b, user_c], users) = match load_users_from_args(&mut args, 3)
+        .await
+        .and_then(|users| Ok((users.first()?, users)))
+    {
+        Ok(users) => users,
+        Err(err) => {
+            println!("Failed to load credentials: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    match check_tokens([&user_a, &user_b, &user_c]) {
+        Ok(warnings) => {
+            for warning in warnings {
+                println!("Warning: {warning}");
             }
         }
         Err(err) => {
-            panic!("{err:?}")
+            println!("Refusing to start: {err}");
+            return ExitCode::FAILURE;
         }
     }
+
+    if let Err(err) = run(&environment, users.labels(), user_a, user_b, user_c).await {
+        println!("Client C failed: {err}");
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+async fn run(
+    environment: &Environment,
+    labels: &[String],
+    user_a: AuthUser,
+    user_b: AuthUser,
+    user_c: AuthUser,
+) -> Result<(), SocialClientError> {
+    let hosts = |index: usize| environment.user_hosts(&labels[index], index);
+    let client_a = ReconnectingClient::for_hosts(hosts(0), user_a);
+    let client_b = ReconnectingClient::for_hosts(hosts(1), user_b);
+    let client_c = ReconnectingClient::for_hosts(hosts(2), user_c);
+    let address_a = client_a.address();
+    let address_b = client_b.address();
+    let address_c = client_c.address();
+
+    println!("C -> B: send request");
+    let response = client_c
+        .call(|client| async move {
+            client
+                .request(address_b, Some("A message".to_string()))
+                .await
+        })
+        .await?;
+    println!("> Server Unary > Response > UpdateFrienshipResponse:: {response:?}");
+
+    println!("Waiting for Matrix to update the status...");
+    client_b
+        .wait_for_friendship_state(
+            address_c,
+            FriendshipState::PendingIncoming,
+            CONVERGENCE_TIMEOUT,
+        )
+        .await?;
+
+    println!("B -> C: accept request");
+    let response = client_b
+        .call(|client| async move { client.accept(address_c).await })
+        .await?;
+    println!("> Server Unary > Response > UpdateFrienshipResponse:: {response:?}");
+
+    println!("A -> C: send request");
+    let response = client_a
+        .call(|client| async move {
+            client
+                .request(address_c, Some("A message".to_string()))
+                .await
+        })
+        .await?;
+    println!("> Server Unary > Response > UpdateFrienshipResponse:: {response:?}");
+
+    println!("Waiting for Matrix to update the status...");
+    client_c
+        .wait_for_friendship_state(
+            address_a,
+            FriendshipState::PendingIncoming,
+            CONVERGENCE_TIMEOUT,
+        )
+        .await?;
+
+    println!("C -> A: accept request");
+    let response = client_c
+        .call(|client| async move { client.accept(address_a).await })
+        .await?;
+    println!("> Server Unary > Response > UpdateFrienshipResponse:: {response:?}");
+
+    println!("Waiting for Matrix to update the status...");
+    client_a
+        .wait_for_friendship_state(address_c, FriendshipState::Friends, CONVERGENCE_TIMEOUT)
+        .await?;
+    client_b
+        .wait_for_friendship_state(address_c, FriendshipState::Friends, CONVERGENCE_TIMEOUT)
+        .await?;
+
+    let mutual_friends = client_a
+        .call(|client| async move { client.get_mutual_friends(address_c).await })
+        .await?;
+    println!(
+        "> Server Streams > Response > GetMutualFriendsResponse for {:?}: {:?}",
+        short_address(address_a),
+        mutual_friends
+    );
+
+    Ok(())
 }
@@ -0,0 +1,315 @@
+use std::{collections::BTreeMap, env, fmt, io};
+
+/// Config file read from the working directory when no other is given.
+pub const DEFAULT_CONFIG_FILE: &str = "social-client.json";
+/// Environment used when none is selected.
+pub const DEFAULT_ENVIRONMENT: &str = "local";
+
+/// Selects the environment, overridden by `--env`.
+pub const ENVIRONMENT_VAR: &str = "SOCIAL_CLIENT_ENV";
+/// Comma separated hosts replacing the ones of the environment, overridden by `--host`.
+pub const HOSTS_VAR: &str = "SOCIAL_CLIENT_HOSTS";
+/// Path of the config file, overridden by `--config`.
+pub const CONFIG_FILE_VAR: &str = "SOCIAL_CLIENT_CONFIG";
+
+/// A named set of social service endpoints.
+///
+/// Users with their own hosts connect to those. The others are assigned to the hosts in order:
+/// the first user connects to the first host, the second user to the second one and so on,
+/// wrapping around, so a single host is shared by every user.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Environment {
+    pub name: String,
+    pub hosts: Vec<String>,
+    /// The hosts of the users given by their label in the credentials, tried in order.
+    pub users: BTreeMap<String, Vec<String>>,
+}
+
+impl Environment {
+    /// The host the user at the given position connects to when it has no hosts of its own.
+    pub fn host(&self, user_index: usize) -> &str {
+        &self.hosts[user_index % self.hosts.len()]
+    }
+
+    /// The hosts the user with the given label and position connects to, its own ones or else
+    /// the one of [`Self::host`].
+    pub fn user_hosts(&self, label: &str, user_index: usize) -> &[String] {
+        match self.users.get(label) {
+            Some(hosts) => hosts,
+            None => std::slice::from_ref(&self.hosts[user_index % self.hosts.len()]),
+        }
+    }
+
+    /// Every host of the environment, without repeating those shared by several users.
+    pub fn all_hosts(&self) -> Vec<&str> {
+        let mut all_hosts: Vec<&str> = vec![];
+        for host in self.hosts.iter().chain(self.users.values().flatten()) {
+            if !all_hosts.contains(&host.as_str()) {
+                all_hosts.push(host);
+            }
+        }
+        all_hosts
+    }
+}
+
+/// The environments known without a config file.
+pub fn builtin_environments() -> BTreeMap<String, Vec<String>> {
+    BTreeMap::from([
+        (
+            // One social service instance per user
+            "local".to_string(),
+            vec![
+                "ws://127.0.0.1:8085".to_string(),
+                "ws://127.0.0.1:8086".to_string(),
+            ],
+        ),
+        (
+            "local-single".to_string(),
+            vec!["ws://127.0.0.1:8085".to_string()],
+        ),
+        (
+            // Through the bastion tunnels described in the README
+            "tunnel".to_string(),
+            vec![
+                "ws://localhost:5000".to_string(),
+                "ws://localhost:5001".to_string(),
+            ],
+        ),
+        (
+            "zone".to_string(),
+            vec!["wss://rpc-social-service.decentraland.zone".to_string()],
+        ),
+    ])
+}
+
+/// Errors returned while resolving the environment.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The config file could not be read.
+    Io { path: String, err: io::Error },
+    /// The config file is not valid.
+    Parse { path: String, message: String },
+    /// A command-line flag was given without its value.
+    MissingValue(&'static str),
+    /// The selected environment isn't built in nor defined in the config file.
+    UnknownEnvironment { name: String, known: Vec<String> },
+    /// The selected environment has no hosts.
+    NoHosts(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, err } => write!(f, "failed to read {path}: {err}"),
+            ConfigError::Parse { path, message } => write!(f, "invalid config {path}: {message}"),
+            ConfigError::MissingValue(flag) => write!(f, "missing value for {flag}"),
+            ConfigError::UnknownEnvironment { name, known } => write!(
+                f,
+                "unknown environment {name:?}, expected one of: {}",
+                known.join(", ")
+            ),
+            ConfigError::NoHosts(name) => write!(f, "environment {name:?} has no hosts"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Resolve the environment to connect to, removing the `--env <name>`, `--host <url>` (repeatable)
+/// and `--config <path>` flags from the given command-line arguments.
+///
+/// Flags take precedence over the `SOCIAL_CLIENT_*` environment variables, which take precedence
+/// over the config file. The config file is a JSON file like:
+///
+/// ```json
+/// {
+///   "default_environment": "tunnel",
+///   "environments": {
+///     "staging": ["ws://localhost:6000", "ws://localhost:6001"],
+///     "split": {
+///       "hosts": ["ws://localhost:6000"],
+///       "users": { "bob": ["ws://localhost:7000", "ws://localhost:7001"] }
+///     }
+///   }
+/// }
+/// ```
+///
+/// Its environments are added to the built-in ones (`local`, `local-single`, `tunnel` and `zone`), replacing those
+/// with the same name. An environment is either a list of hosts or the hosts along with the hosts
+/// of some users by label, see [`Environment`]. Hosts given with `--host` or `SOCIAL_CLIENT_HOSTS`
+/// replace all of them.
+pub fn load_environment(args: &mut Vec<String>) -> Result<Environment, ConfigError> {
+    let flags = Flags::extract(args)?;
+
+    let config = match flags.config.or_else(|| env::var(CONFIG_FILE_VAR).ok()) {
+        Some(path) => ConfigFile::read(&path, true)?,
+        None => ConfigFile::read(DEFAULT_CONFIG_FILE, false)?,
+    };
+
+    let name = flags
+        .environment
+        .or_else(|| env::var(ENVIRONMENT_VAR).ok())
+        .or(config.default_environment)
+        .unwrap_or_else(|| DEFAULT_ENVIRONMENT.to_string());
+
+    let (hosts, users) = if !flags.hosts.is_empty() {
+        (flags.hosts, BTreeMap::new())
+    } else if let Ok(hosts) = env::var(HOSTS_VAR) {
+        (split_hosts(&hosts), BTreeMap::new())
+    } else {
+        let mut environments: BTreeMap<String, EnvironmentHosts> = builtin_environments()
+            .into_iter()
+            .map(|(name, hosts)| {
+                let users = BTreeMap::new();
+                (name, EnvironmentHosts { hosts, users })
+            })
+            .collect();
+        environments.extend(config.environments);
+        match environments.remove(&name) {
+            Some(environment) => (environment.hosts, environment.users),
+            None => {
+                return Err(ConfigError::UnknownEnvironment {
+                    name,
+                    known: environments.into_keys().collect(),
+                })
+            }
+        }
+    };
+
+    if hosts.is_empty() {
+        return Err(ConfigError::NoHosts(name));
+    }
+    Ok(Environment { name, hosts, users })
+}
+
+#[derive(Default)]
+struct Flags {
+    environment: Option<String>,
+    hosts: Vec<String>,
+    config: Option<String>,
+}
+
+impl Flags {
+    fn extract(args: &mut Vec<String>) -> Result<Self, ConfigError> {
+        let mut flags = Flags::default();
+        let mut args_iter = std::mem::take(args).into_iter();
+        while let Some(arg) = args_iter.next() {
+            match arg.as_str() {
+                "--env" => flags.environment = Some(flag_value(&mut args_iter, "--env")?),
+                "--host" => flags
+                    .hosts
+                    .extend(split_hosts(&flag_value(&mut args_iter, "--host")?)),
+                "--config" => flags.config = Some(flag_value(&mut args_iter, "--config")?),
+                _ => args.push(arg),
+            }
+        }
+        Ok(flags)
+    }
+}
+
+fn flag_value(
+    args: &mut impl Iterator<Item = String>,
+    flag: &'static str,
+) -> Result<String, ConfigError> {
+    args.next().ok_or(ConfigError::MissingValue(flag))
+}
+
+#[derive(Default)]
+struct ConfigFile {
+    default_environment: Option<String>,
+    environments: BTreeMap<String, EnvironmentHosts>,
+}
+
+/// The hosts of an environment of the config file, and the hosts of its users by label.
+struct EnvironmentHosts {
+    hosts: Vec<String>,
+    users: BTreeMap<String, Vec<String>>,
+}
+
+impl ConfigFile {
+    /// Read the config file, which may only be missing if it wasn't explicitly given.
+    fn read(path: &str, required: bool) -> Result<Self, ConfigError> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound && !required => {
+                return Ok(ConfigFile::default())
+            }
+            Err(err) => {
+                return Err(ConfigError::Io {
+                    path: path.to_string(),
+                    err,
+                })
+            }
+        };
+        let parse_error = |message: String| ConfigError::Parse {
+            path: path.to_string(),
+            message,
+        };
+
+        let config = serde_json::from_str::<serde_json::Value>(&content)
+            .map_err(|err| parse_error(err.to_string()))?;
+        let default_environment = match &config["default_environment"] {
+            serde_json::Value::Null => None,
+            serde_json::Value::String(name) => Some(name.clone()),
+            _ => return Err(parse_error("default_environment must be a string".into())),
+        };
+        let mut environments = BTreeMap::new();
+        if let Some(entries) = config["environments"].as_object() {
+            for (name, entry) in entries {
+                let invalid = || {
+                    parse_error(format!(
+                        "environment {name:?} must be a list of hosts or an object with its hosts and the hosts of its users"
+                    ))
+                };
+                let environment = match entry {
+                    serde_json::Value::Array(_) => EnvironmentHosts {
+                        hosts: host_list(entry).ok_or_else(invalid)?,
+                        users: BTreeMap::new(),
+                    },
+                    serde_json::Value::Object(_) => {
+                        let mut users = BTreeMap::new();
+                        for (label, hosts) in entry["users"].as_object().into_iter().flatten() {
+                            match host_list(hosts) {
+                                Some(hosts) if !hosts.is_empty() => {
+                                    users.insert(label.clone(), hosts);
+                                }
+                                _ => {
+                                    return Err(parse_error(format!(
+                                        "the hosts of {label:?} in environment {name:?} must be a non-empty list"
+                                    )))
+                                }
+                            }
+                        }
+                        EnvironmentHosts {
+                            hosts: host_list(&entry["hosts"]).ok_or_else(invalid)?,
+                            users,
+                        }
+                    }
+                    _ => return Err(invalid()),
+                };
+                environments.insert(name.clone(), environment);
+            }
+        }
+        Ok(ConfigFile {
+            default_environment,
+            environments,
+        })
+    }
+}
+
+fn host_list(hosts: &serde_json::Value) -> Option<Vec<String>> {
+    hosts
+        .as_array()?
+        .iter()
+        .map(|host| host.as_str().map(str::to_string))
+        .collect()
+}
+
+fn split_hosts(hosts: &str) -> Vec<String> {
+    hosts
+        .split(',')
+        .map(str::trim)
+        .filter(|host| !host.is_empty())
+        .map(str::to_string)
+        .collect()
+}
@@ -1,4 +1,30 @@
-use std::io::{self, Write};
+use std::{
+    env, fmt,
+    io::{self, Read, Write},
+    sync::Arc,
+};
+
+use crate::{
+    credentials_file::CredentialsFile,
+    encryption::EncryptionError,
+    identity::{IdentityError, Wallet},
+    matrix::{write_private, MatrixError, MatrixLogin},
+    secret::Secret,
+};
+
+/// Credentials file read from the working directory when no other source is given.
+pub const DEFAULT_CREDENTIALS_FILE: &str = "credentials.zone.json";
+
+/// Path of the credentials file, or `-` for stdin, overridden by `--credentials`.
+pub const CREDENTIALS_VAR: &str = "SOCIAL_CLIENT_CREDENTIALS";
+/// Fail instead of prompting for missing credentials when set to anything but `0` or `false`,
+/// like `--strict`.
+pub const STRICT_VAR: &str = "SOCIAL_CLIENT_STRICT";
+/// Comma separated labels of the users given by environment variables, `A`, `B`, `C`... if unset.
+pub const USERS_VAR: &str = "SOCIAL_CLIENT_USERS";
+/// Homeserver users with a password but no token log in against, unless the credentials file
+/// gives one.
+pub const HOMESERVER_VAR: &str = "SOCIAL_CLIENT_HOMESERVER";
 
 pub async fn get_input(prompt: &str) -> io::Result<String> {
     print!("{prompt}");
@@ -10,66 +36,706 @@ pub async fn get_input(prompt: &str) -> io::Result<String> {
     Ok(buffer.trim_end().to_owned())
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct AuthUser {
     pub address: String,
-    pub token: String,
-}
-
-pub async fn load_users() -> [AuthUser; 3] {
-    // Read token from file
-    match std::fs::read_to_string("credentials.zone.json") {
-        Ok(it) => {
-            let users = serde_json::from_str::<serde_json::Value>(&it).unwrap();
-            let users = &users["users"];
-
-            let user_a = extract_user(&users[0], "A").await;
-            let user_b = extract_user(&users[1], "B").await;
-            let user_c = extract_user(&users[2], "C").await;
-
-            [user_a, user_b, user_c]
-        }
-        Err(_) => {
-            // If missing read from stdin
-            let token_user_a = get_input("Enter Token for User A: ").await.unwrap();
-            let token_user_b = get_input("Enter Token for User B: ").await.unwrap();
-            let token_user_c = get_input("Enter Token for User C: ").await.unwrap();
-            let user_a_address = get_input("Enter Address for User A: ").await.unwrap();
-            let user_b_address = get_input("Enter Address for User B: ").await.unwrap();
-            let user_c_address = get_input("Enter Address for User C: ").await.unwrap();
-
-            [
-                AuthUser {
-                    address: user_a_address,
-                    token: token_user_a,
-                },
-                AuthUser {
-                    address: user_b_address,
-                    token: token_user_b,
-                },
-                AuthUser {
-                    address: user_c_address,
-                    token: token_user_c,
-                },
-            ]
-        }
-    }
-}
-
-pub async fn extract_user(user: &serde_json::Value, user_id: &str) -> AuthUser {
-    let address = match user["social_user_id"].as_str() {
-        Some(address) => address.to_string(),
-        None => {
-            let message = format!("Enter address for User {user_id}");
-            get_input(&message).await.unwrap()
+    pub token: Secret,
+    refresher: Option<Arc<dyn TokenRefresher>>,
+}
+
+impl AuthUser {
+    pub fn new(address: &str, token: &str) -> Self {
+        Self {
+            address: address.to_string(),
+            token: Secret::from(token),
+            refresher: None,
+        }
+    }
+
+    /// Obtain a new token from the given refresher when the social service rejects this one, see
+    /// [`ReconnectingClient::call`](crate::reconnect::ReconnectingClient::call).
+    pub fn with_token_refresher(mut self, refresher: Arc<dyn TokenRefresher>) -> Self {
+        self.refresher = Some(refresher);
+        self
+    }
+
+    pub fn token_refresher(&self) -> Option<&Arc<dyn TokenRefresher>> {
+        self.refresher.as_ref()
+    }
+}
+
+/// The end of the address, to tell users apart in logs.
+pub fn short_address(address: &str) -> &str {
+    match address.char_indices().rev().nth(3) {
+        Some((index, _)) => &address[index..],
+        None => address,
+    }
+}
+
+impl fmt::Debug for AuthUser {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuthUser")
+            .field("address", &self.address)
+            .field("token", &self.token)
+            .field("refreshable", &self.refresher.is_some())
+            .finish()
+    }
+}
+
+/// Provides a new token for a user whose token was rejected, e.g. because it expired.
+#[async_trait::async_trait]
+pub trait TokenRefresher: Send + Sync {
+    async fn refresh_token(
+        &self,
+        user: &AuthUser,
+    ) -> Result<Secret, Box<dyn std::error::Error + Send + Sync>>;
+}
+
+/// Labelled users, in the order they were loaded.
+#[derive(Clone, Debug, Default)]
+pub struct Users {
+    labels: Vec<String>,
+    users: Vec<AuthUser>,
+}
+
+impl Users {
+    /// Add a user, replacing the one with the same label.
+    pub fn insert(&mut self, label: &str, user: AuthUser) {
+        match self.labels.iter().position(|existing| existing == label) {
+            Some(index) => self.users[index] = user,
+            None => {
+                self.labels.push(label.to_string());
+                self.users.push(user);
+            }
         }
+    }
+
+    pub fn get(&self, label: &str) -> Option<&AuthUser> {
+        let index = self.labels.iter().position(|existing| existing == label)?;
+        Some(&self.users[index])
+    }
+
+    /// The position of the user with the given label, or else with the given address.
+    pub fn position(&self, label_or_address: &str) -> Option<usize> {
+        self.labels
+            .iter()
+            .position(|label| label == label_or_address)
+            .or_else(|| {
+                self.users
+                    .iter()
+                    .position(|user| user.address.eq_ignore_ascii_case(label_or_address))
+            })
+    }
+
+    pub fn len(&self) -> usize {
+        self.users.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.users.is_empty()
+    }
+
+    pub fn labels(&self) -> &[String] {
+        &self.labels
+    }
+
+    pub fn users(&self) -> &[AuthUser] {
+        &self.users
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &AuthUser)> {
+        self.labels
+            .iter()
+            .map(String::as_str)
+            .zip(self.users.iter())
+    }
+
+    /// The first `N` users, or [`CredentialsError::MissingUsers`] if there are less.
+    pub fn first<const N: usize>(&self) -> Result<[AuthUser; N], CredentialsError> {
+        if self.users.len() < N {
+            return Err(CredentialsError::MissingUsers {
+                source: "the credentials".to_string(),
+                expected: N,
+                found: self.users.len(),
+            });
+        }
+        Ok(std::array::from_fn(|index| self.users[index].clone()))
+    }
+}
+
+/// The label given to the user at the given position when it has none: `A` to `Z`, then its
+/// position starting at 1.
+pub fn default_label(index: usize) -> String {
+    match u8::try_from(index) {
+        Ok(index) if index < 26 => char::from(b'A' + index).to_string(),
+        _ => (index + 1).to_string(),
+    }
+}
+
+/// Where the credentials are read from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CredentialsSource {
+    /// The users given by environment variables if any, otherwise `credentials.zone.json` if it
+    /// exists.
+    Default,
+    /// A credentials file, which must exist.
+    File(String),
+    /// A credentials file read from stdin.
+    Stdin,
+    /// The `SOCIAL_CLIENT_USER_<LABEL>_ADDRESS` and `SOCIAL_CLIENT_USER_<LABEL>_TOKEN` variables
+    /// of the users listed in `SOCIAL_CLIENT_USERS`.
+    Env,
+}
+
+/// How to load the credentials.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CredentialsOptions {
+    pub source: CredentialsSource,
+    /// Fail with [`CredentialsError`] instead of prompting for missing users or fields.
+    pub strict: bool,
+}
+
+impl Default for CredentialsOptions {
+    fn default() -> Self {
+        Self {
+            source: CredentialsSource::Default,
+            strict: false,
+        }
+    }
+}
+
+impl CredentialsOptions {
+    /// Read the options removing the `--credentials <path>` (`-` for stdin), `--credentials-env`
+    /// and `--strict` flags from the given command-line arguments, which take precedence over the
+    /// `SOCIAL_CLIENT_CREDENTIALS` and `SOCIAL_CLIENT_STRICT` environment variables.
+    pub fn from_args(args: &mut Vec<String>) -> Result<Self, CredentialsError> {
+        let mut options = CredentialsOptions {
+            source: match env::var(CREDENTIALS_VAR) {
+                Ok(path) => source_from_path(path),
+                Err(_) => CredentialsSource::Default,
+            },
+            strict: env::var(STRICT_VAR).is_ok_and(|strict| strict != "0" && strict != "false"),
+        };
+
+        let mut args_iter = std::mem::take(args).into_iter();
+        while let Some(arg) = args_iter.next() {
+            match arg.as_str() {
+                "--credentials" => {
+                    let path = args_iter
+                        .next()
+                        .ok_or(CredentialsError::MissingValue("--credentials"))?;
+                    options.source = source_from_path(path);
+                }
+                "--credentials-env" => options.source = CredentialsSource::Env,
+                "--strict" => options.strict = true,
+                _ => args.push(arg),
+            }
+        }
+        Ok(options)
+    }
+}
+
+fn source_from_path(path: String) -> CredentialsSource {
+    if path == "-" {
+        CredentialsSource::Stdin
+    } else {
+        CredentialsSource::File(path)
+    }
+}
+
+/// Errors returned while loading the credentials.
+#[derive(Debug)]
+pub enum CredentialsError {
+    /// The credentials could not be read.
+    Io { source: String, err: io::Error },
+    /// The credentials are not a valid credentials file.
+    Parse { source: String, message: String },
+    /// A user has no value for the given field.
+    MissingField {
+        source: String,
+        label: String,
+        field: String,
+    },
+    /// There are less users than needed.
+    MissingUsers {
+        source: String,
+        expected: usize,
+        found: usize,
+    },
+    /// A command-line flag was given without its value.
+    MissingValue(&'static str),
+    /// The missing credentials could not be prompted.
+    Prompt(io::Error),
+    /// A user with a password or a wallet could not log in against the homeserver.
+    Login { label: String, err: MatrixError },
+    /// The private key or keystore of a user could not be loaded.
+    Identity { label: String, err: IdentityError },
+    /// The credentials are encrypted and there is no passphrase to decrypt them.
+    MissingPassphrase(String),
+    /// The credentials could not be decrypted, e.g. because the passphrase is wrong.
+    Decryption {
+        source: String,
+        err: EncryptionError,
+    },
+    /// The credentials could not be encrypted.
+    Encryption {
+        source: String,
+        err: EncryptionError,
+    },
+}
+
+impl fmt::Display for CredentialsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CredentialsError::Io { source, err } => write!(f, "failed to read {source}: {err}"),
+            CredentialsError::Parse { source, message } => {
+                write!(f, "invalid credentials in {source}: {message}")
+            }
+            CredentialsError::MissingField {
+                source,
+                label,
+                field,
+            } => write!(f, "user {label} has no {field} in {source}"),
+            CredentialsError::MissingUsers {
+                source,
+                expected,
+                found,
+            } => write!(f, "expected {expected} users in {source}, found {found}"),
+            CredentialsError::MissingValue(flag) => write!(f, "missing value for {flag}"),
+            CredentialsError::Prompt(err) => write!(f, "failed to prompt for credentials: {err}"),
+            CredentialsError::Login { label, err } => {
+                write!(f, "failed to log in user {label}: {err}")
+            }
+            CredentialsError::Identity { label, err } => {
+                write!(f, "failed to load the wallet of user {label}: {err}")
+            }
+            CredentialsError::MissingPassphrase(source) => write!(
+                f,
+                "{source} is encrypted, set SOCIAL_CLIENT_PASSPHRASE to decrypt it"
+            ),
+            CredentialsError::Decryption { source, err } => {
+                write!(f, "failed to decrypt {source}: {err}")
+            }
+            CredentialsError::Encryption { source, err } => {
+                write!(f, "failed to encrypt {source}: {err}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for CredentialsError {}
+
+/// Load at least `count` users from the given source.
+///
+/// Missing users and fields are prompted for, unless the options are strict or the credentials
+/// come from stdin, in which case they fail with [`CredentialsError`].
+///
+/// Credentials files have a `users` field with either a list of users, labelled by their
+/// optional `label` field or by [`default_label`], or an object of users by label:
+///
+/// ```json
+/// { "users": { "alice": { "social_user_id": "0x...", "access_token": "..." } } }
+/// ```
+///
+/// Instead of an `access_token`, users can have a `password` to log in against the `homeserver`
+/// of the user or of the file, see [`MatrixLogin`].
+pub async fn load_users(
+    options: &CredentialsOptions,
+    count: usize,
+) -> Result<Users, CredentialsError> {
+    let strict = options.strict || options.source == CredentialsSource::Stdin;
+    let (source, mut users) = match &options.source {
+        CredentialsSource::File(path) => (path.clone(), read_file(path, strict).await?),
+        CredentialsSource::Stdin => {
+            let mut content = String::new();
+            io::stdin()
+                .read_to_string(&mut content)
+                .map_err(|err| CredentialsError::Io {
+                    source: "stdin".to_string(),
+                    err,
+                })?;
+            (
+                "stdin".to_string(),
+                parse_users(&content, "stdin", strict).await?,
+            )
+        }
+        CredentialsSource::Env => (
+            "environment variables".to_string(),
+            users_from_env(strict).await?,
+        ),
+        CredentialsSource::Default => {
+            if env::var(USERS_VAR).is_ok() || has_env_user(&default_label(0)) {
+                (
+                    "environment variables".to_string(),
+                    users_from_env(strict).await?,
+                )
+            } else {
+                match read_file(DEFAULT_CREDENTIALS_FILE, strict).await {
+                    Err(CredentialsError::Io { err, .. })
+                        if err.kind() == io::ErrorKind::NotFound =>
+                    {
+                        (DEFAULT_CREDENTIALS_FILE.to_string(), Users::default())
+                    }
+                    result => (DEFAULT_CREDENTIALS_FILE.to_string(), result?),
+                }
+            }
+        }
+    };
+
+    if users.len() < count && strict {
+        return Err(CredentialsError::MissingUsers {
+            source,
+            expected: count,
+            found: users.len(),
+        });
+    }
+
+    // If missing read from stdin
+    let mut index = users.len();
+    while users.len() < count {
+        let label = default_label(index);
+        if users.get(&label).is_none() {
+            let address = prompt(&format!("Enter address for User {label}: ")).await?;
+            let token = prompt(&format!("Enter token for User {label}: ")).await?;
+            users.insert(&label, AuthUser::new(&address, &token));
+        }
+        index += 1;
+    }
+
+    Ok(users)
+}
+
+/// Load at least `count` users with the options of the given command-line arguments, see
+/// [`CredentialsOptions::from_args`].
+pub async fn load_users_from_args(
+    args: &mut Vec<String>,
+    count: usize,
+) -> Result<Users, CredentialsError> {
+    let options = CredentialsOptions::from_args(args)?;
+    load_users(&options, count).await
+}
+
+async fn read_file(path: &str, strict: bool) -> Result<Users, CredentialsError> {
+    let content = std::fs::read_to_string(path).map_err(|err| CredentialsError::Io {
+        source: path.to_string(),
+        err,
+    })?;
+    parse_users(&content, path, strict).await
+}
+
+/// Parse a credentials file, `source` naming it in errors.
+///
+/// Encrypted files are decrypted with the passphrase given by `SOCIAL_CLIENT_PASSPHRASE` or
+/// prompted for, see [`CredentialsFile`].
+pub async fn parse_users(
+    content: &str,
+    source: &str,
+    strict: bool,
+) -> Result<Users, CredentialsError> {
+    let file = CredentialsFile::parse(content, source, strict)?;
+    let (credentials, passphrase) = (file.credentials, file.passphrase);
+
+    let homeserver = match credentials["homeserver"].as_str() {
+        Some(homeserver) => Some(homeserver.to_string()),
+        None => env::var(HOMESERVER_VAR).ok(),
+    };
+
+    let mut users = Users::default();
+    match &credentials["users"] {
+        serde_json::Value::Array(entries) => {
+            for (index, user) in entries.iter().enumerate() {
+                let label = match user["label"].as_str() {
+                    Some(label) => label.to_string(),
+                    None => default_label(index),
+                };
+                let user = extract_user(
+                    user,
+                    &label,
+                    source,
+                    homeserver.as_deref(),
+                    passphrase.as_deref(),
+                    strict,
+                )
+                .await?;
+                users.insert(&label, user);
+            }
+        }
+        serde_json::Value::Object(entries) => {
+            for (label, user) in entries {
+                let user = extract_user(
+                    user,
+                    label,
+                    source,
+                    homeserver.as_deref(),
+                    passphrase.as_deref(),
+                    strict,
+                )
+                .await?;
+                users.insert(label, user);
+            }
+        }
+        _ => {
+            return Err(CredentialsError::Parse {
+                source: source.to_string(),
+                message: "users must be a list or an object".to_string(),
+            })
+        }
+    }
+    Ok(users)
+}
+
+/// Read the users from the `SOCIAL_CLIENT_USER_<LABEL>_ADDRESS` and `SOCIAL_CLIENT_USER_<LABEL>_TOKEN`
+/// environment variables.
+///
+/// Users without a token but with a `SOCIAL_CLIENT_USER_<LABEL>_PASSWORD`, or with a
+/// `SOCIAL_CLIENT_USER_<LABEL>_PRIVATE_KEY` instead of an address, log in against the homeserver
+/// given by `SOCIAL_CLIENT_HOMESERVER`.
+///
+/// The labels are listed in `SOCIAL_CLIENT_USERS`, or default to `A`, `B`, `C`... while there is
+/// an address or a private key for them.
+pub async fn users_from_env(strict: bool) -> Result<Users, CredentialsError> {
+    let labels: Vec<String> = match env::var(USERS_VAR) {
+        Ok(labels) => labels
+            .split(',')
+            .map(str::trim)
+            .filter(|label| !label.is_empty())
+            .map(str::to_string)
+            .collect(),
+        Err(_) => (0..)
+            .map(default_label)
+            .take_while(|label| has_env_user(label))
+            .collect(),
+    };
+
+    let homeserver = |label: &str| {
+        env::var(HOMESERVER_VAR).map_err(|_| CredentialsError::MissingField {
+            source: "environment variables".to_string(),
+            label: label.to_string(),
+            field: HOMESERVER_VAR.to_string(),
+        })
+    };
+
+    let mut users = Users::default();
+    for label in labels {
+        if let Ok(private_key) = env::var(user_var(&label, "PRIVATE_KEY")) {
+            let wallet = Wallet::from_private_key(&private_key)
+                .map_err(|err| identity_error(&label, err))?;
+            let login = MatrixLogin::with_wallet(&homeserver(&label)?, wallet);
+            users.insert(&label, login_user(&label, login).await?);
+            continue;
+        }
+        let address = env_field(&label, "ADDRESS", "address", strict).await?;
+        if let (Err(_), Ok(password)) = (
+            env::var(user_var(&label, "TOKEN")),
+            env::var(user_var(&label, "PASSWORD")),
+        ) {
+            let login = MatrixLogin::new(&homeserver(&label)?, &address, &password);
+            users.insert(&label, login_user(&label, login).await?);
+            continue;
+        }
+        let token = env_field(&label, "TOKEN", "token", strict).await?;
+        users.insert(&label, AuthUser::new(&address, &token));
+    }
+    Ok(users)
+}
+
+fn has_env_user(label: &str) -> bool {
+    env::var(user_var(label, "ADDRESS")).is_ok() || env::var(user_var(label, "PRIVATE_KEY")).is_ok()
+}
+
+/// The name of the environment variable holding the given field of a user.
+pub fn user_var(label: &str, field: &str) -> String {
+    let label: String = label
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("SOCIAL_CLIENT_USER_{label}_{field}")
+}
+
+async fn env_field(
+    label: &str,
+    field: &str,
+    name: &str,
+    strict: bool,
+) -> Result<String, CredentialsError> {
+    let var = user_var(label, field);
+    match env::var(&var) {
+        Ok(value) => Ok(value),
+        Err(_) if strict => Err(CredentialsError::MissingField {
+            source: "environment variables".to_string(),
+            label: label.to_string(),
+            field: var,
+        }),
+        Err(_) => prompt(&format!("Enter {name} for User {label}: ")).await,
+    }
+}
+
+/// Read a user of a credentials file.
+///
+/// A user with a `password` but no `access_token` logs in as its `social_user_id` against its
+/// `homeserver`, or the given default one. So does a user with a `private_key`, or a `keystore`
+/// file decrypted with its `password`, as the address of the wallet, unless it already has an
+/// `access_token`. The sessions of those logins are cached encrypted with `passphrase`, the one of
+/// an encrypted credentials file, so its tokens don't end up in plain text.
+pub async fn extract_user(
+    user: &serde_json::Value,
+    label: &str,
+    source: &str,
+    homeserver: Option<&str>,
+    passphrase: Option<&str>,
+    strict: bool,
+) -> Result<AuthUser, CredentialsError> {
+    let cached = |login: MatrixLogin| login.with_sessions_passphrase(passphrase);
+    let field = |field: &str, name: &str| {
+        let value = user[field].as_str().map(str::to_string);
+        let missing = CredentialsError::MissingField {
+            source: source.to_string(),
+            label: label.to_string(),
+            field: field.to_string(),
+        };
+        let prompt_message = format!("Enter {name} for User {label}: ");
+        async move {
+            match value {
+                Some(value) => Ok(value),
+                None if strict => Err(missing),
+                None => prompt(&prompt_message).await,
+            }
+        }
+    };
+    let homeserver = || {
+        user["homeserver"]
+            .as_str()
+            .or(homeserver)
+            .ok_or_else(|| CredentialsError::MissingField {
+                source: source.to_string(),
+                label: label.to_string(),
+                field: "homeserver".to_string(),
+            })
     };
-    let token = match user["access_token"].as_str() {
-        Some(token) => token.to_string(),
-        None => {
-            let message = format!("Enter token for User {user_id}");
-            get_input(&message).await.unwrap()
+
+    let wallet = match (user["private_key"].as_str(), user["keystore"].as_str()) {
+        (Some(private_key), _) => Some(Wallet::from_private_key(private_key)),
+        (None, Some(keystore)) => {
+            let password = field("password", "keystore password").await?;
+            Some(Wallet::from_keystore(keystore, &password))
         }
+        (None, None) => None,
     };
-    AuthUser { address, token }
+    if let Some(wallet) = wallet {
+        let wallet = wallet.map_err(|err| identity_error(label, err))?;
+        // A token obtained beforehand is used as is, the wallet only logs in again to refresh it
+        return match (user["access_token"].as_str(), homeserver()) {
+            (Some(token), Ok(homeserver)) => Ok(AuthUser::new(&wallet.address(), token)
+                .with_token_refresher(Arc::new(cached(MatrixLogin::with_wallet(
+                    homeserver, wallet,
+                ))))),
+            (Some(token), Err(_)) => Ok(AuthUser::new(&wallet.address(), token)),
+            (None, homeserver) => {
+                login_user(label, cached(MatrixLogin::with_wallet(homeserver?, wallet))).await
+            }
+        };
+    }
+
+    let address = field("social_user_id", "address").await?;
+    if let (None, Some(password)) = (user["access_token"].as_str(), user["password"].as_str()) {
+        let login = cached(MatrixLogin::new(homeserver()?, &address, password));
+        return login_user(label, login).await;
+    }
+    let token = field("access_token", "token").await?;
+    Ok(AuthUser::new(&address, &token))
+}
+
+fn identity_error(label: &str, err: IdentityError) -> CredentialsError {
+    CredentialsError::Identity {
+        label: label.to_string(),
+        err,
+    }
+}
+
+async fn login_user(label: &str, login: MatrixLogin) -> Result<AuthUser, CredentialsError> {
+    Arc::new(login)
+        .auth_user()
+        .await
+        .map_err(|err| CredentialsError::Login {
+            label: label.to_string(),
+            err,
+        })
+}
+
+/// Where and how [`generate_credentials`] logs in the generated users.
+#[derive(Clone, Debug, Default)]
+pub struct GenerateOptions {
+    /// Homeserver written to the credentials file, which the users log in against when loaded.
+    pub homeserver: Option<String>,
+    /// Log in the users while generating them, so their tokens are written to the file. The
+    /// Decentraland homeserver registers the users on their first login.
+    pub log_in: bool,
+    /// Log in through this URL instead of the login endpoint of the homeserver.
+    pub login_url: Option<String>,
+}
+
+/// Generate `count` users with random wallets, labelled [`default_label`], as a credentials file
+/// [`parse_users`] reads.
+pub async fn generate_credentials(
+    count: usize,
+    options: &GenerateOptions,
+) -> Result<serde_json::Value, CredentialsError> {
+    let mut users = Vec::with_capacity(count);
+    for index in 0..count {
+        let label = default_label(index);
+        let wallet = Wallet::random();
+        let mut user = serde_json::json!({
+            "label": label,
+            "social_user_id": wallet.address(),
+            "private_key": wallet.private_key(),
+        });
+
+        if options.log_in {
+            let homeserver =
+                options
+                    .homeserver
+                    .as_deref()
+                    .ok_or_else(|| CredentialsError::MissingField {
+                        source: "generate options".to_string(),
+                        label: label.clone(),
+                        field: "homeserver".to_string(),
+                    })?;
+            let mut login = MatrixLogin::with_wallet(homeserver, wallet).with_sessions_file(None);
+            if let Some(login_url) = &options.login_url {
+                login = login.with_login_url(login_url);
+            }
+            let session = login
+                .session()
+                .await
+                .map_err(|err| CredentialsError::Login {
+                    label: label.clone(),
+                    err,
+                })?;
+            user["access_token"] = session.access_token.expose().into();
+        }
+        users.push(user);
+    }
+
+    let mut credentials = serde_json::json!({ "users": users });
+    if let Some(homeserver) = &options.homeserver {
+        credentials["homeserver"] = homeserver.as_str().into();
+    }
+    Ok(credentials)
+}
+
+/// Write a credentials file only readable by the current user, as it holds private keys and
+/// tokens.
+pub fn write_credentials(path: &str, credentials: &serde_json::Value) -> io::Result<()> {
+    let content = serde_json::to_string_pretty(credentials)?;
+    write_private(path, &content)
+}
+
+async fn prompt(message: &str) -> Result<String, CredentialsError> {
+    get_input(message).await.map_err(CredentialsError::Prompt)
 }
@@ -0,0 +1,168 @@
+use std::{env, fmt};
+
+use serde_json::json;
+
+use crate::{
+    credentials::{default_label, write_credentials, CredentialsError},
+    encryption::{decrypt, encrypt, is_encrypted},
+    secret::Secret,
+};
+
+/// Passphrase of encrypted credentials files, prompted for if unset.
+pub const PASSPHRASE_VAR: &str = "SOCIAL_CLIENT_PASSPHRASE";
+
+/// The content of a credentials file, which may be encrypted at rest with a passphrase.
+#[derive(Clone)]
+pub struct CredentialsFile {
+    /// The decrypted credentials.
+    pub credentials: serde_json::Value,
+    /// The passphrase the file is encrypted with when written, `None` to write it in plain text.
+    pub passphrase: Option<String>,
+}
+
+impl Default for CredentialsFile {
+    fn default() -> Self {
+        Self {
+            credentials: json!({ "users": [] }),
+            passphrase: None,
+        }
+    }
+}
+
+impl fmt::Debug for CredentialsFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The users have tokens, passwords and private keys
+        f.debug_struct("CredentialsFile")
+            .field("credentials", &Secret::default())
+            .field(
+                "passphrase",
+                &self.passphrase.as_ref().map(|_| Secret::default()),
+            )
+            .finish()
+    }
+}
+
+impl CredentialsFile {
+    /// Parse a credentials file, decrypting it with [`read_passphrase`] if it's encrypted.
+    pub fn parse(content: &str, source: &str, strict: bool) -> Result<Self, CredentialsError> {
+        let credentials = parse_json(content, source)?;
+        if !is_encrypted(&credentials) {
+            return Ok(Self {
+                credentials,
+                passphrase: None,
+            });
+        }
+
+        let passphrase = read_passphrase(source, strict)?;
+        let content =
+            decrypt(&credentials, &passphrase).map_err(|err| CredentialsError::Decryption {
+                source: source.to_string(),
+                err,
+            })?;
+        Ok(Self {
+            credentials: parse_json(&content, source)?,
+            passphrase: Some(passphrase),
+        })
+    }
+
+    pub fn read(path: &str, strict: bool) -> Result<Self, CredentialsError> {
+        let content = std::fs::read_to_string(path).map_err(|err| CredentialsError::Io {
+            source: path.to_string(),
+            err,
+        })?;
+        Self::parse(&content, path, strict)
+    }
+
+    /// Write the credentials, encrypted if there is a passphrase.
+    pub fn write(&self, path: &str) -> Result<(), CredentialsError> {
+        let io_error = |err| CredentialsError::Io {
+            source: path.to_string(),
+            err,
+        };
+        match &self.passphrase {
+            Some(passphrase) => {
+                let content = serde_json::to_string_pretty(&self.credentials)
+                    .map_err(|err| io_error(err.into()))?;
+                let envelope =
+                    encrypt(&content, passphrase).map_err(|err| CredentialsError::Encryption {
+                        source: path.to_string(),
+                        err,
+                    })?;
+                write_credentials(path, &envelope).map_err(io_error)
+            }
+            None => write_credentials(path, &self.credentials).map_err(io_error),
+        }
+    }
+
+    /// Add a user, replacing the one with the same label.
+    pub fn add_user(&mut self, label: &str, mut user: serde_json::Value) {
+        match &mut self.credentials["users"] {
+            serde_json::Value::Object(entries) => {
+                entries.insert(label.to_string(), user);
+            }
+            serde_json::Value::Array(entries) => {
+                user["label"] = label.into();
+                match position(entries, label) {
+                    Some(index) => entries[index] = user,
+                    None => entries.push(user),
+                }
+            }
+            users => {
+                user["label"] = label.into();
+                *users = json!([user]);
+            }
+        }
+    }
+
+    /// Remove the user with the given label, returning whether there was one.
+    pub fn remove_user(&mut self, label: &str) -> bool {
+        match &mut self.credentials["users"] {
+            serde_json::Value::Object(entries) => {
+                // Unlike `remove`, keeps the order of the other users
+                let count = entries.len();
+                entries.retain(|existing, _| existing != label);
+                entries.len() < count
+            }
+            serde_json::Value::Array(entries) => {
+                let Some(index) = position(entries, label) else {
+                    return false;
+                };
+                // The users after it would otherwise take the default label of the previous one
+                for (index, entry) in entries.iter_mut().enumerate() {
+                    if entry["label"].is_null() {
+                        entry["label"] = default_label(index).into();
+                    }
+                }
+                entries.remove(index);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The passphrase of an encrypted credentials file, from `SOCIAL_CLIENT_PASSPHRASE` or prompted
+/// for without echo unless strict.
+pub fn read_passphrase(source: &str, strict: bool) -> Result<String, CredentialsError> {
+    match env::var(PASSPHRASE_VAR) {
+        Ok(passphrase) => Ok(passphrase),
+        Err(_) if strict => Err(CredentialsError::MissingPassphrase(source.to_string())),
+        Err(_) => rpassword::prompt_password(format!("Enter passphrase for {source}: "))
+            .map_err(CredentialsError::Prompt),
+    }
+}
+
+fn position(entries: &[serde_json::Value], label: &str) -> Option<usize> {
+    entries.iter().enumerate().position(|(index, entry)| {
+        entry["label"]
+            .as_str()
+            .map_or(default_label(index) == label, |existing| existing == label)
+    })
+}
+
+fn parse_json(content: &str, source: &str) -> Result<serde_json::Value, CredentialsError> {
+    serde_json::from_str(content).map_err(|err| CredentialsError::Parse {
+        source: source.to_string(),
+        message: err.to_string(),
+    })
+}
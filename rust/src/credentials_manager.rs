@@ -0,0 +1,167 @@
+use social_client::credentials::{
+    load_users, CredentialsError, CredentialsOptions, CredentialsSource, DEFAULT_CREDENTIALS_FILE,
+};
+use social_client::credentials_file::{CredentialsFile, PASSPHRASE_VAR};
+use social_client::token::inspect_token;
+use std::{env, io, process::ExitCode};
+
+const USAGE: &str = "Usage: credentials_manager [--credentials <path>] <command>
+
+Commands:
+  encrypt                 Encrypt the credentials file with a passphrase
+  decrypt                 Decrypt the credentials file
+  add <label> [fields]    Add or replace a user, with --address, --token, --password,
+                          --private-key, --keystore and --homeserver
+  remove <label>          Remove a user
+  inspect                 Decode the tokens of the users, from any credentials source";
+
+/// The flags of `add` and the credentials file fields they set.
+const FIELDS: [(&str, &str); 6] = [
+    ("--address", "social_user_id"),
+    ("--token", "access_token"),
+    ("--password", "password"),
+    ("--private-key", "private_key"),
+    ("--keystore", "keystore"),
+    ("--homeserver", "homeserver"),
+];
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let options = match CredentialsOptions::from_args(&mut args) {
+        Ok(options) => options,
+        Err(err) => {
+            println!("{err}\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+    if args == ["inspect"] {
+        return inspect(&options).await;
+    }
+    let path = match options.source {
+        CredentialsSource::Default => DEFAULT_CREDENTIALS_FILE.to_string(),
+        CredentialsSource::File(path) => path,
+        CredentialsSource::Stdin | CredentialsSource::Env => {
+            println!("Only credentials files can be managed");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if let Err(err) = run(&path, &args, options.strict) {
+        println!("{err}");
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+fn run(path: &str, args: &[String], strict: bool) -> Result<(), String> {
+    let Some((command, args)) = args.split_first() else {
+        return Err(USAGE.to_string());
+    };
+    match (command.as_str(), args) {
+        ("encrypt", []) => {
+            let mut file = CredentialsFile::read(path, strict).map_err(|err| err.to_string())?;
+            if file.passphrase.is_some() {
+                return Err(format!("{path} is already encrypted"));
+            }
+            file.passphrase = Some(new_passphrase(strict).map_err(|err| err.to_string())?);
+            file.write(path).map_err(|err| err.to_string())?;
+            println!("Encrypted {path}");
+        }
+        ("decrypt", []) => {
+            let mut file = CredentialsFile::read(path, strict).map_err(|err| err.to_string())?;
+            if file.passphrase.take().is_none() {
+                return Err(format!("{path} is not encrypted"));
+            }
+            file.write(path).map_err(|err| err.to_string())?;
+            println!("Decrypted {path}");
+        }
+        ("add", [label, fields @ ..]) => {
+            let user = user_from_flags(fields)?;
+            let mut file = match CredentialsFile::read(path, strict) {
+                Err(CredentialsError::Io { err, .. }) if err.kind() == io::ErrorKind::NotFound => {
+                    CredentialsFile::default()
+                }
+                file => file.map_err(|err| err.to_string())?,
+            };
+            file.add_user(label, user);
+            file.write(path).map_err(|err| err.to_string())?;
+            println!("Added user {label} to {path}");
+        }
+        ("remove", [label]) => {
+            let mut file = CredentialsFile::read(path, strict).map_err(|err| err.to_string())?;
+            if !file.remove_user(label) {
+                return Err(format!("There is no user {label} in {path}"));
+            }
+            file.write(path).map_err(|err| err.to_string())?;
+            println!("Removed user {label} from {path}");
+        }
+        _ => return Err(USAGE.to_string()),
+    }
+    Ok(())
+}
+
+/// Print the decoded tokens, failing if the credentials can't be loaded or a token expired.
+async fn inspect(options: &CredentialsOptions) -> ExitCode {
+    let users = match load_users(options, 0).await {
+        Ok(users) => users,
+        Err(err) => {
+            println!("Failed to load credentials: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut status = ExitCode::SUCCESS;
+    for (label, user) in users.iter() {
+        let info = inspect_token(user.token.expose());
+        println!("User {label} ({}): {info}", user.address);
+        for warning in info.warnings(&user.address) {
+            println!("  Warning: {warning}");
+        }
+        if info.is_expired() {
+            println!("  Error: the token expired");
+            status = ExitCode::FAILURE;
+        }
+    }
+    status
+}
+
+fn user_from_flags(flags: &[String]) -> Result<serde_json::Value, String> {
+    let mut user = serde_json::Map::new();
+    let mut flags = flags.iter();
+    while let Some(flag) = flags.next() {
+        let Some((_, field)) = FIELDS.iter().find(|(name, _)| name == flag) else {
+            return Err(format!("Unexpected argument {flag}\n{USAGE}"));
+        };
+        let value = flags
+            .next()
+            .ok_or_else(|| format!("Missing value for {flag}"))?;
+        user.insert(field.to_string(), value.as_str().into());
+    }
+    if user.is_empty() {
+        return Err(format!("No fields given for the user\n{USAGE}"));
+    }
+    Ok(user.into())
+}
+
+/// The passphrase to encrypt with, from `SOCIAL_CLIENT_PASSPHRASE` or prompted for twice.
+fn new_passphrase(strict: bool) -> Result<String, CredentialsError> {
+    if let Ok(passphrase) = env::var(PASSPHRASE_VAR) {
+        return Ok(passphrase);
+    }
+    if strict {
+        return Err(CredentialsError::MissingValue(PASSPHRASE_VAR));
+    }
+    loop {
+        let passphrase =
+            rpassword::prompt_password("New passphrase: ").map_err(CredentialsError::Prompt)?;
+        let confirmation =
+            rpassword::prompt_password("Confirm passphrase: ").map_err(CredentialsError::Prompt)?;
+        if passphrase.is_empty() {
+            println!("The passphrase can't be empty");
+        } else if passphrase != confirmation {
+            println!("The passphrases don't match");
+        } else {
+            return Ok(passphrase);
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use std::fmt;
+
+use aes_gcm::{
+    aead::{Aead, KeyInit},
+    Aes256Gcm, Nonce,
+};
+use rand::RngCore;
+use serde_json::json;
+
+/// Version of the encrypted format, bumped on incompatible changes.
+pub const ENCRYPTION_VERSION: u64 = 1;
+
+const CIPHER: &str = "aes-256-gcm";
+const KDF: &str = "scrypt";
+// The scrypt parameters recommended by its authors for interactive logins
+const LOG_N: u8 = 15;
+const R: u32 = 8;
+const P: u32 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// Whether the given credentials are an encrypted envelope rather than plain credentials.
+pub fn is_encrypted(credentials: &serde_json::Value) -> bool {
+    credentials["encrypted"].is_object()
+}
+
+/// Encrypt the given content with a key derived from the passphrase with scrypt, returning an
+/// envelope like:
+///
+/// ```json
+/// { "encrypted": { "version": 1, "cipher": "aes-256-gcm", "kdf": "scrypt", "log_n": 15, "r": 8, "p": 1, "salt": "...", "nonce": "...", "ciphertext": "..." } }
+/// ```
+pub fn encrypt(content: &str, passphrase: &str) -> Result<serde_json::Value, EncryptionError> {
+    let mut salt = [0; SALT_LEN];
+    let mut nonce = [0; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut salt);
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let cipher = derive_cipher(passphrase, &salt, LOG_N, R, P)?;
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), content.as_bytes())
+        .map_err(|_| EncryptionError::Cipher)?;
+    Ok(json!({
+        "encrypted": {
+            "version": ENCRYPTION_VERSION,
+            "cipher": CIPHER,
+            "kdf": KDF,
+            "log_n": LOG_N,
+            "r": R,
+            "p": P,
+            "salt": hex::encode(salt),
+            "nonce": hex::encode(nonce),
+            "ciphertext": hex::encode(ciphertext),
+        }
+    }))
+}
+
+/// Decrypt an envelope written by [`encrypt`].
+pub fn decrypt(
+    credentials: &serde_json::Value,
+    passphrase: &str,
+) -> Result<String, EncryptionError> {
+    let envelope = &credentials["encrypted"];
+    let invalid = |message: &str| EncryptionError::InvalidFormat(message.to_string());
+    if envelope["version"].as_u64() != Some(ENCRYPTION_VERSION) {
+        return Err(EncryptionError::UnsupportedVersion(
+            envelope["version"].to_string(),
+        ));
+    }
+    if envelope["cipher"] != CIPHER || envelope["kdf"] != KDF {
+        return Err(invalid("unsupported cipher or kdf"));
+    }
+    let bytes = |field: &str| {
+        envelope[field]
+            .as_str()
+            .and_then(|value| hex::decode(value).ok())
+            .ok_or_else(|| invalid(&format!("{field} must be a hex string")))
+    };
+    let number = |field: &str| {
+        envelope[field]
+            .as_u64()
+            .and_then(|value| u32::try_from(value).ok())
+            .ok_or_else(|| invalid(&format!("{field} must be a number")))
+    };
+    let log_n = u8::try_from(number("log_n")?).map_err(|_| invalid("log_n is too large"))?;
+    let nonce = bytes("nonce")?;
+    if nonce.len() != NONCE_LEN {
+        return Err(invalid("nonce must be 12 bytes long"));
+    }
+
+    let cipher = derive_cipher(
+        passphrase,
+        &bytes("salt")?,
+        log_n,
+        number("r")?,
+        number("p")?,
+    )?;
+    let content = cipher
+        .decrypt(Nonce::from_slice(&nonce), bytes("ciphertext")?.as_slice())
+        .map_err(|_| EncryptionError::WrongPassphrase)?;
+    String::from_utf8(content).map_err(|_| invalid("the content is not UTF-8"))
+}
+
+fn derive_cipher(
+    passphrase: &str,
+    salt: &[u8],
+    log_n: u8,
+    r: u32,
+    p: u32,
+) -> Result<Aes256Gcm, EncryptionError> {
+    let params = scrypt::Params::new(log_n, r, p)
+        .map_err(|err| EncryptionError::InvalidFormat(format!("invalid scrypt params: {err}")))?;
+    let mut key = [0; 32];
+    scrypt::scrypt(passphrase.as_bytes(), salt, &params, &mut key)
+        .map_err(|_| EncryptionError::Cipher)?;
+    Aes256Gcm::new_from_slice(&key).map_err(|_| EncryptionError::Cipher)
+}
+
+/// Errors returned while encrypting or decrypting credentials.
+#[derive(Debug)]
+pub enum EncryptionError {
+    /// The envelope was written by an unknown version.
+    UnsupportedVersion(String),
+    /// The envelope is missing fields or has invalid ones.
+    InvalidFormat(String),
+    /// The passphrase is wrong, or the ciphertext was modified.
+    WrongPassphrase,
+    /// The cipher could not process the content.
+    Cipher,
+}
+
+impl fmt::Display for EncryptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncryptionError::UnsupportedVersion(version) => {
+                write!(f, "unsupported encryption version {version}")
+            }
+            EncryptionError::InvalidFormat(message) => write!(f, "invalid envelope: {message}"),
+            EncryptionError::WrongPassphrase => write!(f, "wrong passphrase or corrupted content"),
+            EncryptionError::Cipher => write!(f, "cipher failure"),
+        }
+    }
+}
+
+impl std::error::Error for EncryptionError {}
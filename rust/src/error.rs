@@ -0,0 +1,180 @@
+use std::{
+    fmt,
+    time::{Duration, SystemTime},
+};
+
+use dcl_rpc::client::ClientResultError;
+
+use crate::{
+    friendship_state::{FriendshipAction, FriendshipState},
+    BadRequestError, ForbiddenError, InternalServerError, TooManyRequestsError, UnauthorizedError,
+};
+
+/// Errors returned by the social client procedures.
+#[derive(Debug)]
+pub enum SocialClientError {
+    /// The WebSocket connection to the social service could not be established.
+    Connection(String),
+    /// The `friendships` port could not be created on the RPC server.
+    PortCreation(ClientResultError),
+    /// The `FriendshipsService` module could not be loaded from the port.
+    ModuleLoading(ClientResultError),
+    /// The procedure call failed in the RPC transport or in the RPC server.
+    Rpc(ClientResultError),
+    /// The social service processed the call but answered with an error.
+    Friendship(FriendshipError),
+    /// The social service rejected the synapse token.
+    Unauthorized(String),
+    /// The social service answered without any response variant set.
+    EmptyResponse(&'static str),
+    /// The procedure didn't complete in time, the connection is probably dead.
+    Timeout(Duration),
+    /// The friendship with `address` didn't reach the expected state in time.
+    StateNotConverged {
+        address: String,
+        expected: FriendshipState,
+        observed: Option<FriendshipState>,
+        timeout: Duration,
+    },
+    /// The friendship event update isn't allowed in the current state of the friendship with
+    /// `address`, so it wasn't sent.
+    InvalidTransition {
+        action: FriendshipAction,
+        address: String,
+        state: FriendshipState,
+    },
+    /// Every reconnection attempt failed.
+    ReconnectGaveUp {
+        attempts: u32,
+        last_error: Box<SocialClientError>,
+    },
+    /// The token of `address` expired, so nothing was sent with it.
+    TokenExpired {
+        address: String,
+        expired_at: SystemTime,
+    },
+}
+
+impl SocialClientError {
+    /// Whether the error was caused by the connection, so the same call may succeed on a new one.
+    pub fn is_connection_error(&self) -> bool {
+        matches!(
+            self,
+            SocialClientError::Connection(_)
+                | SocialClientError::PortCreation(_)
+                | SocialClientError::ModuleLoading(_)
+                | SocialClientError::Rpc(ClientResultError::Client(_))
+                | SocialClientError::Timeout(_)
+        )
+    }
+}
+
+/// Errors returned by the social service in the `response` oneof of each procedure.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FriendshipError {
+    BadRequest(String),
+    Forbidden(String),
+    TooManyRequests(String),
+    InternalServer(String),
+}
+
+impl fmt::Display for SocialClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SocialClientError::Connection(err) => write!(f, "failed to connect: {err}"),
+            SocialClientError::PortCreation(err) => write!(f, "failed to create port: {err:?}"),
+            SocialClientError::ModuleLoading(err) => write!(f, "failed to load module: {err:?}"),
+            SocialClientError::Rpc(err) => write!(f, "rpc call failed: {err:?}"),
+            SocialClientError::Friendship(err) => write!(f, "social service error: {err}"),
+            SocialClientError::Unauthorized(message) => write!(f, "unauthorized: {message}"),
+            SocialClientError::EmptyResponse(procedure) => {
+                write!(f, "empty response from {procedure}")
+            }
+            SocialClientError::Timeout(timeout) => write!(f, "timed out after {timeout:?}"),
+            SocialClientError::StateNotConverged {
+                address,
+                expected,
+                observed,
+                timeout,
+            } => write!(
+                f,
+                "friendship with {address} didn't become {expected:?} within {timeout:?}, last observed {observed:?}"
+            ),
+            SocialClientError::InvalidTransition {
+                action,
+                address,
+                state,
+            } => write!(
+                f,
+                "cannot {action}: {} {address}",
+                action.rejection_reason(*state)
+            ),
+            SocialClientError::ReconnectGaveUp {
+                attempts,
+                last_error,
+            } => write!(
+                f,
+                "gave up reconnecting after {attempts} attempts: {last_error}"
+            ),
+            SocialClientError::TokenExpired {
+                address,
+                expired_at,
+            } => write!(
+                f,
+                "the token of {address} expired at {}",
+                humantime::format_rfc3339_seconds(*expired_at)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SocialClientError {}
+
+impl fmt::Display for FriendshipError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FriendshipError::BadRequest(message) => write!(f, "bad request: {message}"),
+            FriendshipError::Forbidden(message) => write!(f, "forbidden: {message}"),
+            FriendshipError::TooManyRequests(message) => write!(f, "too many requests: {message}"),
+            FriendshipError::InternalServer(message) => {
+                write!(f, "internal server error: {message}")
+            }
+        }
+    }
+}
+
+impl From<ClientResultError> for SocialClientError {
+    fn from(err: ClientResultError) -> Self {
+        SocialClientError::Rpc(err)
+    }
+}
+
+impl From<UnauthorizedError> for SocialClientError {
+    fn from(err: UnauthorizedError) -> Self {
+        SocialClientError::Unauthorized(err.message)
+    }
+}
+
+impl From<BadRequestError> for SocialClientError {
+    fn from(err: BadRequestError) -> Self {
+        SocialClientError::Friendship(FriendshipError::BadRequest(err.message))
+    }
+}
+
+impl From<ForbiddenError> for SocialClientError {
+    fn from(err: ForbiddenError) -> Self {
+        SocialClientError::Friendship(FriendshipError::Forbidden(err.message))
+    }
+}
+
+impl From<TooManyRequestsError> for SocialClientError {
+    fn from(err: TooManyRequestsError) -> Self {
+        SocialClientError::Friendship(FriendshipError::TooManyRequests(err.message))
+    }
+}
+
+impl From<InternalServerError> for SocialClientError {
+    fn from(err: InternalServerError) -> Self {
+        SocialClientError::Friendship(FriendshipError::InternalServer(err.message))
+    }
+}
@@ -0,0 +1,316 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    mem::Discriminant,
+    sync::Arc,
+    time::Duration,
+};
+
+use crate::{
+    error::SocialClientError,
+    friendship_event_response,
+    friendship_procedures::events_from_response,
+    reconnect::{ReconnectingClient, Subscription},
+    AcceptResponse, CancelResponse, DeleteResponse, FriendshipEventResponse, RejectResponse,
+    RequestEvents, RequestResponse, User,
+};
+
+/// A friendship events updates subscription that survives reconnections.
+///
+/// When the connection is lost, the stream re-subscribes and compares the request events and
+/// friends of the user against the last known state, emitting the events that happened in the
+/// meantime. The events of the first update received after re-subscribing are skipped if the
+/// reconciliation already emitted them.
+///
+/// Reconciled events are reconstructed from state differences, so an action performed by the
+/// user itself from another client (e.g. cancelling a sent request) is reported as the closest
+/// event coming from the other user (e.g. a rejection), and events cancelling each other out
+/// (e.g. a request sent and cancelled while disconnected) aren't reported.
+pub struct FriendshipEventStream {
+    client: Arc<ReconnectingClient>,
+    idle_timeout: Duration,
+    subscription: Option<Subscription>,
+    state: Option<FriendshipsState>,
+    pending: VecDeque<FriendshipEventResponse>,
+    /// Events emitted by the last reconciliation, by address and kind, that the first update after
+    /// re-subscribing may repeat.
+    reconciled: HashMap<EventKey, FriendshipEventResponse>,
+}
+
+impl FriendshipEventStream {
+    pub fn new(client: Arc<ReconnectingClient>) -> Self {
+        Self {
+            client,
+            idle_timeout: Duration::from_secs(20),
+            subscription: None,
+            state: None,
+            pending: VecDeque::new(),
+            reconciled: HashMap::new(),
+        }
+    }
+
+    /// After this long without updates the connection is checked and re-established if dead.
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// Wait for the next friendship event, reconnecting as needed.
+    ///
+    /// Fails if the server rejects the subscription or the client gives up reconnecting.
+    pub async fn next(&mut self) -> Result<FriendshipEventResponse, SocialClientError> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Ok(event);
+            }
+
+            let Some(subscription) = self.subscription.as_mut() else {
+                self.subscribe().await?;
+                continue;
+            };
+
+            match tokio::time::timeout(self.idle_timeout, subscription.next()).await {
+                Ok(Some(response)) => {
+                    let state = self.state.get_or_insert_with(FriendshipsState::default);
+                    // Only the first update after re-subscribing may repeat reconciled events
+                    let reconciled = std::mem::take(&mut self.reconciled);
+                    for event in events_from_response(response)? {
+                        let Some(key) = event_key(&event) else {
+                            continue;
+                        };
+                        state.apply(&key.0, &event);
+                        if !matches!(reconciled.get(&key), Some(reconciled) if is_same_event(reconciled, &event))
+                        {
+                            self.pending.push_back(event);
+                        }
+                    }
+                }
+                Ok(None) => {
+                    log::warn!("Friendship events subscription closed, re-subscribing...");
+                    self.drop_subscription().await;
+                }
+                Err(_) => {
+                    if !self.is_alive().await {
+                        log::warn!("Friendship events subscription timed out, re-subscribing...");
+                        self.drop_subscription().await;
+                    }
+                }
+            }
+        }
+    }
+
+    async fn subscribe(&mut self) -> Result<(), SocialClientError> {
+        let subscription = self.client.subscribe_friendship_events_updates().await?;
+        self.subscription = Some(subscription);
+
+        // Fetch the state after subscribing, so nothing happening in between is missed
+        let current = self.fetch_state().await?;
+        match self.state.as_mut() {
+            Some(previous) => {
+                self.reconciled.clear();
+                for event in previous.reconcile(current) {
+                    if let Some(key) = event_key(&event) {
+                        self.reconciled.insert(key, event.clone());
+                    }
+                    self.pending.push_back(event);
+                }
+            }
+            None => self.state = Some(current),
+        }
+        Ok(())
+    }
+
+    async fn fetch_state(&self) -> Result<FriendshipsState, SocialClientError> {
+        let request_events = self
+            .client
+            .call(|client| async move { client.get_request_events().await })
+            .await?;
+        let friends = self
+            .client
+            .call(|client| async move { client.get_friends().await })
+            .await?;
+        Ok(FriendshipsState::new(request_events, friends))
+    }
+
+    /// Check the connection with a cheap procedure, since a dead connection doesn't end the stream.
+    async fn is_alive(&self) -> bool {
+        let Some(subscription) = self.subscription.as_ref() else {
+            return false;
+        };
+        let probe = tokio::time::timeout(
+            self.client.request_timeout(),
+            subscription.client().get_request_events(),
+        )
+        .await;
+        match probe {
+            Ok(Ok(_)) => true,
+            Ok(Err(err)) => !err.is_connection_error(),
+            Err(_) => false,
+        }
+    }
+
+    async fn drop_subscription(&mut self) {
+        if let Some(subscription) = self.subscription.take() {
+            self.client.disconnect(subscription.client()).await;
+        }
+    }
+}
+
+/// The friendships of the user as last seen by the stream, keyed by lowercase address.
+#[derive(Debug, Default, Clone)]
+struct FriendshipsState {
+    friends: HashSet<String>,
+    incoming: HashMap<String, RequestResponse>,
+    outgoing: HashSet<String>,
+}
+
+impl FriendshipsState {
+    fn new(request_events: RequestEvents, friends: Vec<User>) -> Self {
+        let incoming = request_events
+            .incoming
+            .map(|requests| requests.items)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|request| Some((key(&request.user)?, request)))
+            .collect();
+        let outgoing = request_events
+            .outgoing
+            .map(|requests| requests.items)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|request| key(&request.user))
+            .collect();
+        let friends = friends
+            .into_iter()
+            .map(|user| user.address.to_lowercase())
+            .collect();
+        Self {
+            friends,
+            incoming,
+            outgoing,
+        }
+    }
+
+    /// Apply an event received from the subscription for the given address.
+    fn apply(&mut self, address: &str, event: &FriendshipEventResponse) {
+        match &event.body {
+            Some(friendship_event_response::Body::Request(request)) => {
+                self.incoming.insert(address.to_string(), request.clone());
+            }
+            Some(friendship_event_response::Body::Accept(_)) => {
+                self.outgoing.remove(address);
+                self.friends.insert(address.to_string());
+            }
+            Some(friendship_event_response::Body::Reject(_)) => {
+                self.outgoing.remove(address);
+            }
+            Some(friendship_event_response::Body::Cancel(_)) => {
+                self.incoming.remove(address);
+            }
+            Some(friendship_event_response::Body::Delete(_)) => {
+                self.friends.remove(address);
+            }
+            None => {}
+        }
+    }
+
+    /// Replace the state with the current one, returning the events that explain the difference.
+    fn reconcile(&mut self, current: FriendshipsState) -> Vec<FriendshipEventResponse> {
+        let mut events = vec![];
+
+        for (address, request) in &current.incoming {
+            if self.incoming.get(address) != Some(request) {
+                events.push(body(friendship_event_response::Body::Request(
+                    request.clone(),
+                )));
+            }
+        }
+        for address in self.incoming.keys() {
+            if !current.incoming.contains_key(address) && !current.friends.contains(address) {
+                events.push(body(friendship_event_response::Body::Cancel(
+                    CancelResponse {
+                        user: Some(user(address)),
+                    },
+                )));
+            }
+        }
+        for address in &self.outgoing {
+            if !current.outgoing.contains(address) && !current.friends.contains(address) {
+                events.push(body(friendship_event_response::Body::Reject(
+                    RejectResponse {
+                        user: Some(user(address)),
+                    },
+                )));
+            }
+        }
+        for address in &current.friends {
+            // A friend coming from an incoming request was accepted by the user itself
+            if !self.friends.contains(address) && !self.incoming.contains_key(address) {
+                events.push(body(friendship_event_response::Body::Accept(
+                    AcceptResponse {
+                        user: Some(user(address)),
+                    },
+                )));
+            }
+        }
+        for address in &self.friends {
+            if !current.friends.contains(address) {
+                events.push(body(friendship_event_response::Body::Delete(
+                    DeleteResponse {
+                        user: Some(user(address)),
+                    },
+                )));
+            }
+        }
+
+        *self = current;
+        events
+    }
+}
+
+fn key(user: &Option<User>) -> Option<String> {
+    user.as_ref().map(|user| user.address.to_lowercase())
+}
+
+/// The lowercase address of the other user of an event.
+fn event_address(event: &FriendshipEventResponse) -> Option<String> {
+    let user = match event.body.as_ref()? {
+        friendship_event_response::Body::Request(request) => &request.user,
+        friendship_event_response::Body::Accept(accept) => &accept.user,
+        friendship_event_response::Body::Reject(reject) => &reject.user,
+        friendship_event_response::Body::Cancel(cancel) => &cancel.user,
+        friendship_event_response::Body::Delete(delete) => &delete.user,
+    };
+    key(user)
+}
+
+/// The lowercase address of the other user of an event and the kind of the event.
+type EventKey = (String, Discriminant<friendship_event_response::Body>);
+
+fn event_key(event: &FriendshipEventResponse) -> Option<EventKey> {
+    let kind = std::mem::discriminant(event.body.as_ref()?);
+    Some((event_address(event)?, kind))
+}
+
+/// Whether both events are the same kind of event for the same request or user.
+fn is_same_event(a: &FriendshipEventResponse, b: &FriendshipEventResponse) -> bool {
+    use friendship_event_response::Body;
+    let same_kind = match (&a.body, &b.body) {
+        (Some(Body::Request(a)), Some(Body::Request(b))) => a.created_at == b.created_at,
+        (Some(Body::Accept(_)), Some(Body::Accept(_)))
+        | (Some(Body::Reject(_)), Some(Body::Reject(_)))
+        | (Some(Body::Cancel(_)), Some(Body::Cancel(_)))
+        | (Some(Body::Delete(_)), Some(Body::Delete(_))) => true,
+        _ => false,
+    };
+    same_kind && event_address(a) == event_address(b)
+}
+
+fn user(address: &str) -> User {
+    User {
+        address: address.to_string(),
+    }
+}
+
+fn body(body: friendship_event_response::Body) -> FriendshipEventResponse {
+    FriendshipEventResponse { body: Some(body) }
+}
@@ -1,88 +1,85 @@
-use dcl_rpc::transports::web_sockets::tungstenite::{TungsteniteWebSocket, WebSocketClient};
-use dcl_rpc::{client::RpcClient, transports::web_sockets::WebSocketTransport};
-use social_client::{credentials::load_users, FriendshipsServiceClientDefinition};
-use social_client::{FriendshipsServiceClient, Payload};
+use std::env;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
 
-const RECONNECT_DELAY: u64 = 10; // seconds
-const TIMEOUT_RESPONSE: u64 = 20; // seconds
+use social_client::config::load_environment;
+use social_client::credentials::{load_users_from_args, short_address, AuthUser};
+use social_client::error::SocialClientError;
+use social_client::event_stream::FriendshipEventStream;
+use social_client::reconnect::ReconnectingClient;
+use social_client::token::check_tokens;
 
-type Transport = WebSocketTransport<TungsteniteWebSocket, ()>;
+const TIMEOUT_RESPONSE: u64 = 20; // seconds
 
 #[tokio::main]
-async fn main() {
-    // Auth Users
-    let [user_a, user_b, _] = load_users().await;
-
-    let which_a = format!("USER_A_{}", &user_a.address[user_a.address.len() - 4..]);
-    let which_b = format!("USER_B_{}", &user_b.address[user_b.address.len() - 4..]);
-
+async fn main() -> ExitCode {
     // Hosts
-    let host_a = "ws://localhost:8085";
-    let host_b = "ws://localhost:8085";
-
-    let handle_a = tokio::spawn(async move {
-        loop {
-            handle_connection(host_a, &user_a.token, &which_a).await;
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let environment = match load_environment(&mut args) {
+        Ok(environment) => environment,
+        Err(err) => {
+            println!("Invalid configuration: {err}");
+            return ExitCode::FAILURE;
         }
-    });
+    };
 
-    let handle_b = tokio::spawn(async move {
-        loop {
-            handle_connection(host_b, &user_b.token, &which_b).await;
+    // Auth Users
+    let ([user_a, user_b], users) = match load_users_from_args(&mut args, 2)
+        .await
+        .and_then(|users| Ok((users.first()?, users)))
+    {
+        Ok(users) => users,
+        Err(err) => {
+            println!("Failed to load credentials: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    match check_tokens([&user_a, &user_b]) {
+        Ok(warnings) => {
+            for warning in warnings {
+                println!("Warning: {warning}");
+            }
         }
-    });
+        Err(err) => {
+            println!("Refusing to start: {err}");
+            return ExitCode::FAILURE;
+        }
+    }
 
-    let _ = tokio::try_join!(handle_a, handle_b);
-}
+    let which_a = format!("USER_A_{}", short_address(&user_a.address));
+    let which_b = format!("USER_B_{}", short_address(&user_b.address));
 
-async fn handle_connection(host: &str, token: &str, which: &str) {
-    loop {
-        match WebSocketClient::connect(host).await {
-            Ok(client_connection) => {
-                let client_transport = WebSocketTransport::new(client_connection.clone());
+    let hosts_a = environment.user_hosts(&users.labels()[0], 0).to_vec();
+    let hosts_b = environment.user_hosts(&users.labels()[1], 1).to_vec();
 
-                let mut client = RpcClient::new(client_transport).await.unwrap();
+    let handle_a = tokio::spawn(async move { listen(&hosts_a, user_a, which_a).await });
 
-                let port = client.create_port("friendships").await.unwrap();
+    let handle_b = tokio::spawn(async move { listen(&hosts_b, user_b, which_b).await });
 
-                let module = port
-                    .load_module::<FriendshipsServiceClient<Transport>>("FriendshipsService")
-                    .await
-                    .unwrap();
+    match tokio::try_join!(handle_a, handle_b) {
+        Ok((Ok(()), Ok(()))) => ExitCode::SUCCESS,
+        _ => ExitCode::FAILURE,
+    }
+}
 
-                // 4. Listen to updates to my address
-                let updates_response = tokio::time::timeout(
-                    tokio::time::Duration::from_secs(TIMEOUT_RESPONSE),
-                    module.subscribe_friendship_events_updates(Payload {
-                        synapse_token: Some(token.to_string()),
-                    }),
-                )
-                .await;
-                match updates_response {
-                    Ok(Ok(mut u)) => {
-                        println!(
-                            "> Server Streams > Response > Notifications > {which} > Listening..."
-                        );
-                        while let Ok(Some(update)) = tokio::time::timeout(
-                            tokio::time::Duration::from_secs(TIMEOUT_RESPONSE),
-                            u.next(),
-                        )
-                        .await
-                        {
-                            println!("> Server Streams > Response > Notifications > {which} > {update:?}");
-                        }
-                        println!("Timeout when waiting for response, reconnecting...");
-                    }
-                    _ => {
-                        println!("Error with the connection, reconnecting...");
-                        break;
-                    }
-                }
+async fn listen(hosts: &[String], user: AuthUser, which: String) -> Result<(), SocialClientError> {
+    let which_state = which.clone();
+    let client = ReconnectingClient::for_hosts(hosts, user)
+        .on_state_change(move |state| println!("> Connection > {which_state} > {state:?}"));
+
+    // 4. Listen to updates to my address
+    let mut updates = FriendshipEventStream::new(Arc::new(client))
+        .with_idle_timeout(Duration::from_secs(TIMEOUT_RESPONSE));
+    println!("> Server Streams > Response > Notifications > {which} > Listening...");
+    loop {
+        match updates.next().await {
+            Ok(update) => {
+                println!("> Server Streams > Response > Notifications > {which} > {update:?}");
             }
             Err(err) => {
-                println!("Failed to connect, retrying in {RECONNECT_DELAY} seconds...");
-                println!("Error: {err:?}");
-                tokio::time::sleep(tokio::time::Duration::from_secs(RECONNECT_DELAY)).await;
+                println!("> Server Streams > Response > Notifications > {which} > Error: {err}");
+                return Err(err);
             }
         }
     }
@@ -1,20 +1,23 @@
-use dcl_rpc::transports::web_sockets::{tungstenite::TungsteniteWebSocket, WebSocketTransport};
-
-use std::time::Duration;
-use tokio::time::sleep;
+use dcl_rpc::transports::Transport;
 
 use crate::{
-    credentials::AuthUser, friendship_event_payload, AcceptPayload, CancelPayload, DeletePayload,
-    FriendshipEventPayload, FriendshipsServiceClient, FriendshipsServiceClientDefinition, Payload,
-    RejectPayload, RequestPayload, UpdateFriendshipPayload, User,
+    credentials::AuthUser,
+    error::SocialClientError,
+    friendship_event_payload,
+    friendship_state::{validate_transition, wait_for_friendship, FriendshipAction},
+    request_events_response,
+    secret::Secret,
+    subscribe_friendship_events_updates_response,
+    token::check_token,
+    update_friendship_response, users_response, AcceptPayload, CancelPayload, DeletePayload,
+    FriendshipEventPayload, FriendshipEventResponse, FriendshipsServiceClient,
+    FriendshipsServiceClientDefinition, MutualFriendsPayload, Payload, RejectPayload,
+    RequestEvents, RequestEventsResponse, RequestPayload, SubscribeFriendshipEventsUpdatesResponse,
+    UpdateFriendshipPayload, UpdateFriendshipResponse, User, UsersResponse,
 };
 
-type Transport = WebSocketTransport<TungsteniteWebSocket, ()>;
-
-const DELAY: u64 = 5; // seconds
-
 // Define different flows
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Flow {
     /// Request A-B, Cancel A-B
     Flow1,
@@ -33,6 +36,7 @@ pub enum Flow {
 
 impl Flow {
     /// Get the flow from a string.
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Option<Flow> {
         match s {
             "flow1" => Some(Flow::Flow1),
@@ -50,225 +54,377 @@ impl Flow {
 
     /// Execute the flow with the given users and module clients for A and B respectively.
     /// Executing a flow means sending friendship event updates to the server.
-    pub async fn execute_flow(
+    /// Returns the responses of every update in the order they were sent.
+    ///
+    /// Fails with [`SocialClientError::TokenExpired`] before sending anything if the token of
+    /// either user already expired.
+    pub async fn execute_flow<T: Transport + 'static>(
         &self,
-        module_a: &FriendshipsServiceClient<Transport>,
-        module_b: &FriendshipsServiceClient<Transport>,
+        module_a: &FriendshipsServiceClient<T>,
+        module_b: &FriendshipsServiceClient<T>,
         user_a: AuthUser,
         user_b: AuthUser,
-    ) {
-        match self {
+    ) -> Result<Vec<UpdateFriendshipResponse>, SocialClientError> {
+        check_token(&user_a)?;
+        check_token(&user_b)?;
+
+        let responses = match self {
             Flow::Flow1 => {
                 // Implement Flow 1: Request A-B, Cancel A-B
-                request(module_a, &user_a.token, &user_b.address).await;
-                cancel(module_a, &user_a.token, &user_b.address).await;
+                vec![
+                    request(module_a, &user_a, module_b, &user_b).await?,
+                    cancel(module_a, &user_a, module_b, &user_b).await?,
+                ]
             }
             Flow::Flow2 => {
                 // Implement Flow 2: Request A-B, Accept B-A, Delete A-B
-                request(module_a, &user_a.token, &user_b.address).await;
-                accept(module_b, &user_b.token, &user_a.address).await;
-                delete(module_a, &user_a.token, &user_b.address).await;
+                vec![
+                    request(module_a, &user_a, module_b, &user_b).await?,
+                    accept(module_b, &user_b, module_a, &user_a).await?,
+                    delete(module_a, &user_a, module_b, &user_b).await?,
+                ]
             }
             Flow::Flow3 => {
                 // Implement Flow 3: Request A-B, Reject B-A
-                request(module_a, &user_a.token, &user_b.address).await;
-                reject(module_b, &user_b.token, &user_a.address).await;
+                vec![
+                    request(module_a, &user_a, module_b, &user_b).await?,
+                    reject(module_b, &user_b, module_a, &user_a).await?,
+                ]
             }
             Flow::Flow4 => {
                 // Implement Flow 4: Request A-B, Accept A-B, Delete B-A
-                request(module_a, &user_a.token, &user_b.address).await;
-                accept(module_b, &user_b.token, &user_a.address).await;
-                delete(module_b, &user_b.token, &user_a.address).await;
+                vec![
+                    request(module_a, &user_a, module_b, &user_b).await?,
+                    accept(module_b, &user_b, module_a, &user_a).await?,
+                    delete(module_b, &user_b, module_a, &user_a).await?,
+                ]
             }
             _ => {
                 // Do nothing for other Flow variants
+                vec![]
             }
-        }
+        };
+        Ok(responses)
     }
 
     /// Execute a friendship event update with the given users and module client.
-    pub async fn execute_event(
+    /// Returns `None` for the flow variants, which are not a single event.
+    ///
+    /// Like [`Self::execute_flow`], fails with [`SocialClientError::TokenExpired`] before sending
+    /// anything if the token of either user already expired.
+    pub async fn execute_event<T: Transport + 'static>(
         &self,
-        module: &FriendshipsServiceClient<Transport>,
+        module: &FriendshipsServiceClient<T>,
         user_a: AuthUser,
         user_b: AuthUser,
-    ) {
-        match self {
+    ) -> Result<Option<UpdateFriendshipResponse>, SocialClientError> {
+        check_token(&user_a)?;
+        check_token(&user_b)?;
+
+        let response = match self {
             Flow::Request => {
                 // Implement Request A-B
-                request(module, &user_a.token, &user_b.address).await;
+                request(module, &user_a, module, &user_b).await?
             }
             Flow::Accept => {
                 // Implement Accept B-A
-                accept(module, &user_b.token, &user_a.address).await;
+                accept(module, &user_b, module, &user_a).await?
             }
             Flow::Reject => {
                 // Implement Reject B-A
-                reject(module, &user_b.token, &user_a.address).await;
+                reject(module, &user_b, module, &user_a).await?
             }
             Flow::Delete => {
                 // Implement Delete A-B
-                delete(module, &user_a.token, &user_b.address).await;
+                delete(module, &user_a, module, &user_b).await?
             }
             Flow::Cancel => {
                 // Implement Cancel A-B
-                cancel(module, &user_a.token, &user_b.address).await;
+                cancel(module, &user_a, module, &user_b).await?
             }
             _ => {
                 // Do nothing for other Flow variants
+                return Ok(None);
             }
-        }
+        };
+        Ok(Some(response))
     }
 }
 
-async fn request(module: &FriendshipsServiceClient<Transport>, token: &str, user_address: &str) {
+async fn request<T: Transport + 'static>(
+    module: &FriendshipsServiceClient<T>,
+    user: &AuthUser,
+    other_module: &FriendshipsServiceClient<T>,
+    other_user: &AuthUser,
+) -> Result<UpdateFriendshipResponse, SocialClientError> {
+    let expected =
+        validate_transition(module, user, &other_user.address, FriendshipAction::Request).await?;
+
     let request_payload = RequestPayload {
         user: Some(User {
-            address: user_address.to_string(),
+            address: other_user.address.clone(),
         }),
         message: Some("A message".to_string()),
     };
 
-    update_friendship_event(
+    let response = update_friendship_event(
         module,
-        token,
+        &user.token,
         friendship_event_payload::Body::Request(request_payload),
     )
-    .await;
+    .await?;
+
+    // The state resolution from synapse takes some time
+    wait_for_friendship(module, user, other_module, other_user, expected).await?;
+    Ok(response)
 }
 
-async fn cancel(module: &FriendshipsServiceClient<Transport>, token: &str, user_address: &str) {
+async fn cancel<T: Transport + 'static>(
+    module: &FriendshipsServiceClient<T>,
+    user: &AuthUser,
+    other_module: &FriendshipsServiceClient<T>,
+    other_user: &AuthUser,
+) -> Result<UpdateFriendshipResponse, SocialClientError> {
+    let expected =
+        validate_transition(module, user, &other_user.address, FriendshipAction::Cancel).await?;
+
     let cancel_payload = CancelPayload {
         user: Some(User {
-            address: user_address.to_string(),
+            address: other_user.address.clone(),
         }),
     };
 
-    update_friendship_event(
+    let response = update_friendship_event(
         module,
-        token,
+        &user.token,
         friendship_event_payload::Body::Cancel(cancel_payload),
     )
-    .await;
+    .await?;
+
+    // The state resolution from synapse takes some time
+    wait_for_friendship(module, user, other_module, other_user, expected).await?;
+    Ok(response)
 }
 
-async fn accept(module: &FriendshipsServiceClient<Transport>, token: &str, user_address: &str) {
+async fn accept<T: Transport + 'static>(
+    module: &FriendshipsServiceClient<T>,
+    user: &AuthUser,
+    other_module: &FriendshipsServiceClient<T>,
+    other_user: &AuthUser,
+) -> Result<UpdateFriendshipResponse, SocialClientError> {
+    let expected =
+        validate_transition(module, user, &other_user.address, FriendshipAction::Accept).await?;
+
     let accept_payload = AcceptPayload {
         user: Some(User {
-            address: user_address.to_string(),
+            address: other_user.address.clone(),
         }),
     };
 
-    update_friendship_event(
+    let response = update_friendship_event(
         module,
-        token,
+        &user.token,
         friendship_event_payload::Body::Accept(accept_payload),
     )
-    .await;
+    .await?;
+
+    // The state resolution from synapse takes some time
+    wait_for_friendship(module, user, other_module, other_user, expected).await?;
+    Ok(response)
 }
 
-async fn reject(module: &FriendshipsServiceClient<Transport>, token: &str, user_address: &str) {
+async fn reject<T: Transport + 'static>(
+    module: &FriendshipsServiceClient<T>,
+    user: &AuthUser,
+    other_module: &FriendshipsServiceClient<T>,
+    other_user: &AuthUser,
+) -> Result<UpdateFriendshipResponse, SocialClientError> {
+    let expected =
+        validate_transition(module, user, &other_user.address, FriendshipAction::Reject).await?;
+
     let reject_payload = RejectPayload {
         user: Some(User {
-            address: user_address.to_string(),
+            address: other_user.address.clone(),
         }),
     };
 
-    update_friendship_event(
+    let response = update_friendship_event(
         module,
-        token,
+        &user.token,
         friendship_event_payload::Body::Reject(reject_payload),
     )
-    .await;
+    .await?;
+
+    // The state resolution from synapse takes some time
+    wait_for_friendship(module, user, other_module, other_user, expected).await?;
+    Ok(response)
 }
 
-async fn delete(module: &FriendshipsServiceClient<Transport>, token: &str, user_address: &str) {
+async fn delete<T: Transport + 'static>(
+    module: &FriendshipsServiceClient<T>,
+    user: &AuthUser,
+    other_module: &FriendshipsServiceClient<T>,
+    other_user: &AuthUser,
+) -> Result<UpdateFriendshipResponse, SocialClientError> {
+    let expected =
+        validate_transition(module, user, &other_user.address, FriendshipAction::Delete).await?;
+
     let delete_payload = DeletePayload {
         user: Some(User {
-            address: user_address.to_string(),
+            address: other_user.address.clone(),
         }),
     };
 
-    update_friendship_event(
+    let response = update_friendship_event(
         module,
-        token,
+        &user.token,
         friendship_event_payload::Body::Delete(delete_payload),
     )
-    .await;
+    .await?;
+
+    // The state resolution from synapse takes some time
+    wait_for_friendship(module, user, other_module, other_user, expected).await?;
+    Ok(response)
 }
 
 /// Update the friendship event of the given user using the given module client.
-async fn update_friendship_event(
-    module: &FriendshipsServiceClient<Transport>,
-    token: &str,
+pub(crate) async fn update_friendship_event<T: Transport + 'static>(
+    module: &FriendshipsServiceClient<T>,
+    token: &Secret,
     body: friendship_event_payload::Body,
-) {
+) -> Result<UpdateFriendshipResponse, SocialClientError> {
     let event_payload = FriendshipEventPayload { body: Some(body) };
     let response = module
         .update_friendship_event(UpdateFriendshipPayload {
             event: Some(event_payload),
-            auth_token: Some(Payload {
-                synapse_token: Some(token.to_string()),
-            }),
+            auth_token: Some(auth_payload(token)),
         })
-        .await;
-    match response {
-        Ok(response) => {
-            println!("> Server Unary > Response > UpdateFrienshipResponse:: {response:?}");
-        }
-        Err(err) => {
-            panic!("{err:?}");
-        }
+        .await?;
+    check_update_friendship_response(&response)?;
+    Ok(response)
+}
+
+/// The payload authenticating a procedure with the given token.
+///
+/// The generated `Payload` derives `Debug` with the token in it, so it must never be printed.
+pub(crate) fn auth_payload(token: &Secret) -> Payload {
+    Payload {
+        synapse_token: Some(token.expose().to_string()),
     }
+}
 
-    // The state resolution from synapse takes some time
-    sleep(Duration::from_secs(DELAY)).await;
+/// Get the friends of the given user using the given module client.
+pub async fn get_friends<T: Transport + 'static>(
+    module: &FriendshipsServiceClient<T>,
+    user: &AuthUser,
+) -> Result<Vec<User>, SocialClientError> {
+    let mut friends_response = module.get_friends(auth_payload(&user.token)).await?;
+    let mut friends = vec![];
+    while let Some(response) = friends_response.next().await {
+        friends.extend(users_from_response(response, "GetFriends")?);
+    }
+    Ok(friends)
 }
 
-/// Get and print the friends of the given user using the given module client.
-pub async fn get_friends(module: &FriendshipsServiceClient<Transport>, user: &AuthUser) {
-    let friends_response = module
-        .get_friends(Payload {
-            synapse_token: Some(user.clone().token),
+/// Get the mutual friends between the given user and the given address using the given module client.
+pub async fn get_mutual_friends<T: Transport + 'static>(
+    module: &FriendshipsServiceClient<T>,
+    user: &AuthUser,
+    address: &str,
+) -> Result<Vec<User>, SocialClientError> {
+    let mut friends_response = module
+        .get_mutual_friends(MutualFriendsPayload {
+            user: Some(User {
+                address: address.to_string(),
+            }),
+            auth_token: Some(auth_payload(&user.token)),
         })
-        .await;
-    match friends_response {
-        Ok(mut friends_response) => {
-            println!(
-                "> Server Streams > Response > GetAllFriendsResponse for {:?}",
-                &user.clone().address[user.address.len() - 4..]
-            );
-            while let Some(friend) = friends_response.next().await {
-                println!(
-                    "> Server Streams > Response > GetAllFriendsResponse for {:?}: {:?}",
-                    &user.clone().address[user.address.len() - 4..],
-                    friend.response
-                )
-            }
+        .await?;
+    let mut friends = vec![];
+    while let Some(response) = friends_response.next().await {
+        friends.extend(users_from_response(response, "GetMutualFriends")?);
+    }
+    Ok(friends)
+}
+
+/// Get the friendship request events of the given user using the given module client.
+pub async fn get_request_events<T: Transport + 'static>(
+    module: &FriendshipsServiceClient<T>,
+    user: &AuthUser,
+) -> Result<RequestEvents, SocialClientError> {
+    let friendship_request_events = module.get_request_events(auth_payload(&user.token)).await?;
+    request_events_from_response(friendship_request_events)
+}
+
+fn check_update_friendship_response(
+    response: &UpdateFriendshipResponse,
+) -> Result<(), SocialClientError> {
+    match &response.response {
+        Some(update_friendship_response::Response::Event(_)) => Ok(()),
+        Some(update_friendship_response::Response::UnauthorizedError(err)) => {
+            Err(err.clone().into())
+        }
+        Some(update_friendship_response::Response::BadRequestError(err)) => Err(err.clone().into()),
+        Some(update_friendship_response::Response::ForbiddenError(err)) => Err(err.clone().into()),
+        Some(update_friendship_response::Response::TooManyRequestsError(err)) => {
+            Err(err.clone().into())
         }
-        Err(err) => {
-            panic!("{err:?}")
+        Some(update_friendship_response::Response::InternalServerError(err)) => {
+            Err(err.clone().into())
         }
+        None => Err(SocialClientError::EmptyResponse("UpdateFriendshipEvent")),
     }
 }
 
-/// Get and print the friendship request events of the given user using the given module client.
-pub async fn get_request_events(module: &FriendshipsServiceClient<Transport>, user: &AuthUser) {
-    let friendship_request_events = module
-        .get_request_events(Payload {
-            synapse_token: Some(user.clone().token),
-        })
-        .await;
-    match friendship_request_events {
-        Ok(friendship_request_events) => {
-            println!(
-                "> Server Unary > Response > GetRequestsResponse for {:?}: {:?}",
-                &user.clone().address[user.address.len() - 4..],
-                friendship_request_events
-            );
+fn users_from_response(
+    response: UsersResponse,
+    procedure: &'static str,
+) -> Result<Vec<User>, SocialClientError> {
+    match response.response {
+        Some(users_response::Response::Users(users)) => Ok(users.users),
+        Some(users_response::Response::UnauthorizedError(err)) => Err(err.into()),
+        Some(users_response::Response::ForbiddenError(err)) => Err(err.into()),
+        Some(users_response::Response::TooManyRequestsError(err)) => Err(err.into()),
+        Some(users_response::Response::InternalServerError(err)) => Err(err.into()),
+        None => Err(SocialClientError::EmptyResponse(procedure)),
+    }
+}
+
+fn request_events_from_response(
+    response: RequestEventsResponse,
+) -> Result<RequestEvents, SocialClientError> {
+    match response.response {
+        Some(request_events_response::Response::Events(events)) => Ok(events),
+        Some(request_events_response::Response::UnauthorizedError(err)) => Err(err.into()),
+        Some(request_events_response::Response::ForbiddenError(err)) => Err(err.into()),
+        Some(request_events_response::Response::TooManyRequestsError(err)) => Err(err.into()),
+        Some(request_events_response::Response::InternalServerError(err)) => Err(err.into()),
+        None => Err(SocialClientError::EmptyResponse("GetRequestEvents")),
+    }
+}
+
+/// Get the friendship events of a friendship events updates subscription response.
+pub(crate) fn events_from_response(
+    response: SubscribeFriendshipEventsUpdatesResponse,
+) -> Result<Vec<FriendshipEventResponse>, SocialClientError> {
+    match response.response {
+        Some(subscribe_friendship_events_updates_response::Response::Events(events)) => {
+            Ok(events.responses)
+        }
+        Some(subscribe_friendship_events_updates_response::Response::UnauthorizedError(err)) => {
+            Err(err.into())
+        }
+        Some(subscribe_friendship_events_updates_response::Response::ForbiddenError(err)) => {
+            Err(err.into())
+        }
+        Some(subscribe_friendship_events_updates_response::Response::TooManyRequestsError(err)) => {
+            Err(err.into())
         }
-        Err(err) => {
-            panic!("{err:?}");
+        Some(subscribe_friendship_events_updates_response::Response::InternalServerError(err)) => {
+            Err(err.into())
         }
+        None => Err(SocialClientError::EmptyResponse(
+            "SubscribeFriendshipEventsUpdates",
+        )),
     }
 }
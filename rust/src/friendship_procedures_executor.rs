@@ -1,76 +1,131 @@
-use dcl_rpc::transports::web_sockets::tungstenite::WebSocketClient;
-use dcl_rpc::{
-    client::RpcClient,
-    transports::web_sockets::{tungstenite::TungsteniteWebSocket, WebSocketTransport},
-};
-use social_client::friendship_procedures::{get_friends, get_request_events, Flow};
-use social_client::{credentials::load_users, FriendshipsServiceClient};
-use std::env;
-
-type Transport = WebSocketTransport<TungsteniteWebSocket, ()>;
-
-const RECONNECT_DELAY: u64 = 10; // seconds
+use social_client::config::load_environment;
+use social_client::credentials::{load_users_from_args, short_address};
+use social_client::friendship_procedures::Flow;
+use social_client::reconnect::ReconnectingClient;
+use social_client::token::check_tokens;
+use std::{env, process::ExitCode};
 
 #[tokio::main]
-async fn main() {
-    // Get the flow to execute from command-line arguments
-    let args: Vec<String> = env::args().collect();
-    let flow = if args.len() >= 2 {
-        Flow::from_str(&args[1])
-    } else {
-        println!("No flow provided");
-        None
+async fn main() -> ExitCode {
+    // Hosts
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let environment = match load_environment(&mut args) {
+        Ok(environment) => environment,
+        Err(err) => {
+            println!("Invalid configuration: {err}");
+            return ExitCode::FAILURE;
+        }
     };
 
     // Auth Users
-    let [user_a, user_b, _] = load_users().await;
+    let ([user_a, user_b], users) = match load_users_from_args(&mut args, 2)
+        .await
+        .and_then(|users| Ok((users.first()?, users)))
+    {
+        Ok(users) => users,
+        Err(err) => {
+            println!("Failed to load credentials: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
 
-    let host_a = "ws://127.0.0.1:8085";
-    let host_b = "ws://127.0.0.1:8086";
-    // let host_a = "wss://rpc-social-service.decentraland.zone";
+    // Get the flow to execute from the arguments left once the credentials flags are removed
+    let flow = match args.first() {
+        Some(name) => match Flow::from_str(name) {
+            Some(flow) => Some(flow),
+            None => {
+                println!("Unknown flow {name}, expected flow1 to flow4, request, accept, reject, delete or cancel");
+                return ExitCode::FAILURE;
+            }
+        },
+        None => {
+            println!("No flow provided");
+            None
+        }
+    };
 
-    loop {
-        match (
-            WebSocketClient::connect(host_a).await,
-            WebSocketClient::connect(host_b).await,
-        ) {
-            (Ok(client_connection_a), Ok(client_connection_b)) => {
-                let client_transport_a = WebSocketTransport::new(client_connection_a.clone());
-                let client_transport_b = WebSocketTransport::new(client_connection_b.clone());
+    match check_tokens([&user_a, &user_b]) {
+        Ok(warnings) => {
+            for warning in warnings {
+                println!("Warning: {warning}");
+            }
+        }
+        Err(err) => {
+            println!("Refusing to start: {err}");
+            return ExitCode::FAILURE;
+        }
+    }
 
-                let mut client_a = RpcClient::new(client_transport_a).await.unwrap();
-                let mut client_b = RpcClient::new(client_transport_b).await.unwrap();
+    let hosts_a = environment.user_hosts(&users.labels()[0], 0);
+    let hosts_b = environment.user_hosts(&users.labels()[1], 1);
 
-                let port_a = client_a.create_port("friendships").await.unwrap();
-                let port_b = client_b.create_port("friendships").await.unwrap();
+    let reconnecting_a = ReconnectingClient::for_hosts(hosts_a, user_a.clone())
+        .on_state_change(|state| println!("> Connection > USER_A > {state:?}"));
+    let reconnecting_b = ReconnectingClient::for_hosts(hosts_b, user_b.clone())
+        .on_state_change(|state| println!("> Connection > USER_B > {state:?}"));
+
+    loop {
+        let (client_a, client_b) =
+            match (reconnecting_a.client().await, reconnecting_b.client().await) {
+                (Ok(client_a), Ok(client_b)) => (client_a, client_b),
+                (Err(err), _) | (_, Err(err)) => {
+                    println!("Failed to connect: {err}");
+                    return ExitCode::FAILURE;
+                }
+            };
 
-                let module_a = port_a
-                    .load_module::<FriendshipsServiceClient<Transport>>("FriendshipsService")
-                    .await
-                    .unwrap();
-                let module_b = port_b
-                    .load_module::<FriendshipsServiceClient<Transport>>("FriendshipsService")
-                    .await
-                    .unwrap();
+        let which_a = short_address(&user_a.address);
 
-                // 1. Get Friends message
-                get_friends(&module_a, &user_a).await;
+        // 1. Get Friends message
+        match reconnecting_a
+            .call(|client| async move { client.get_friends().await })
+            .await
+        {
+            Ok(friends) => println!(
+                "> Server Streams > Response > GetAllFriendsResponse for {which_a:?}: {friends:?}"
+            ),
+            Err(err) => println!("Failed to get friends: {err}"),
+        }
 
-                // 2. Get Friendship Request Events message
-                get_request_events(&module_a, &user_a).await;
+        // 2. Get Friendship Request Events message
+        match reconnecting_a
+            .call(|client| async move { client.get_request_events().await })
+            .await
+        {
+            Ok(events) => println!(
+                "> Server Unary > Response > GetRequestsResponse for {which_a:?}: {events:?}"
+            ),
+            Err(err) => println!("Failed to get request events: {err}"),
+        }
 
-                // 3. Update Friendship Events message
-                if let Some(flow) = flow.clone() {
-                    flow.execute_flow(&module_a, &module_b, user_a.clone(), user_b.clone())
-                        .await;
-                } else {
-                    // Do nothing
+        // 3. Update Friendship Events message
+        if let Some(flow) = flow.clone() {
+            match flow
+                .execute_flow(
+                    client_a.module(),
+                    client_b.module(),
+                    user_a.clone(),
+                    user_b.clone(),
+                )
+                .await
+            {
+                Ok(responses) => {
+                    for response in responses {
+                        println!(
+                            "> Server Unary > Response > UpdateFrienshipResponse:: {response:?}"
+                        );
+                    }
+                }
+                Err(err) => {
+                    println!("Failed to execute flow: {err}");
+                    if err.is_connection_error() {
+                        reconnecting_a.disconnect(&client_a).await;
+                        reconnecting_b.disconnect(&client_b).await;
+                    }
                 }
             }
-            _ => {
-                println!("Failed to connect, retrying in {RECONNECT_DELAY} seconds...");
-                tokio::time::sleep(tokio::time::Duration::from_secs(RECONNECT_DELAY)).await;
-            }
+        } else {
+            // Do nothing
         }
     }
 }
@@ -0,0 +1,282 @@
+use std::{fmt, future::Future, time::Duration};
+
+use dcl_rpc::transports::Transport;
+use tokio::time::{sleep, timeout, Instant};
+
+use crate::{
+    credentials::AuthUser,
+    error::SocialClientError,
+    friendship_event_payload, friendship_event_response,
+    friendship_procedures::{get_friends, get_request_events},
+    FriendshipsServiceClient, RequestEvents, Requests, User,
+};
+
+/// How long to wait for the social service to reflect a friendship event update.
+pub const CONVERGENCE_TIMEOUT: Duration = Duration::from_secs(30);
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The friendship between a user and another one, as seen by the first user.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FriendshipState {
+    None,
+    /// The user sent a friendship request to the other one.
+    PendingOutgoing,
+    /// The user received a friendship request from the other one.
+    PendingIncoming,
+    Friends,
+}
+
+/// A friendship event update sent by a user to another one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FriendshipAction {
+    Request,
+    Accept,
+    Reject,
+    Cancel,
+    Delete,
+}
+
+impl FriendshipAction {
+    /// The action of a friendship event update and the address it's sent to.
+    pub fn from_body(body: &friendship_event_payload::Body) -> (Self, Option<&str>) {
+        let (action, user) = match body {
+            friendship_event_payload::Body::Request(request) => {
+                (FriendshipAction::Request, &request.user)
+            }
+            friendship_event_payload::Body::Accept(accept) => {
+                (FriendshipAction::Accept, &accept.user)
+            }
+            friendship_event_payload::Body::Reject(reject) => {
+                (FriendshipAction::Reject, &reject.user)
+            }
+            friendship_event_payload::Body::Cancel(cancel) => {
+                (FriendshipAction::Cancel, &cancel.user)
+            }
+            friendship_event_payload::Body::Delete(delete) => {
+                (FriendshipAction::Delete, &delete.user)
+            }
+        };
+        (action, user.as_ref().map(|user| user.address.as_str()))
+    }
+
+    /// The action of a friendship event and the address of the other user.
+    pub fn from_event(body: &friendship_event_response::Body) -> (Self, Option<&str>) {
+        let (action, user) = match body {
+            friendship_event_response::Body::Request(request) => {
+                (FriendshipAction::Request, &request.user)
+            }
+            friendship_event_response::Body::Accept(accept) => {
+                (FriendshipAction::Accept, &accept.user)
+            }
+            friendship_event_response::Body::Reject(reject) => {
+                (FriendshipAction::Reject, &reject.user)
+            }
+            friendship_event_response::Body::Cancel(cancel) => {
+                (FriendshipAction::Cancel, &cancel.user)
+            }
+            friendship_event_response::Body::Delete(delete) => {
+                (FriendshipAction::Delete, &delete.user)
+            }
+        };
+        (action, user.as_ref().map(|user| user.address.as_str()))
+    }
+
+    /// Why the action can't be performed on the given friendship, followed by the other address.
+    pub(crate) fn rejection_reason(self, state: FriendshipState) -> &'static str {
+        match (self, state) {
+            (FriendshipAction::Request, FriendshipState::Friends) => "already friends with",
+            (FriendshipAction::Request, FriendshipState::PendingOutgoing) => {
+                "already sent a request to"
+            }
+            (FriendshipAction::Request, _) => "already received a request from",
+            (FriendshipAction::Accept | FriendshipAction::Reject, _) => "no pending request from",
+            (FriendshipAction::Cancel, _) => "no pending request to",
+            (FriendshipAction::Delete, _) => "not friends with",
+        }
+    }
+}
+
+impl fmt::Display for FriendshipAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let action = match self {
+            FriendshipAction::Request => "request",
+            FriendshipAction::Accept => "accept",
+            FriendshipAction::Reject => "reject",
+            FriendshipAction::Cancel => "cancel",
+            FriendshipAction::Delete => "delete",
+        };
+        f.write_str(action)
+    }
+}
+
+impl FriendshipState {
+    /// The friendship after the user performs the given action, or `Option::None` if the action
+    /// isn't allowed in this state.
+    pub fn transition(self, action: FriendshipAction) -> Option<Self> {
+        match (self, action) {
+            (FriendshipState::None, FriendshipAction::Request) => {
+                Some(FriendshipState::PendingOutgoing)
+            }
+            (FriendshipState::PendingOutgoing, FriendshipAction::Cancel) => {
+                Some(FriendshipState::None)
+            }
+            (FriendshipState::PendingIncoming, FriendshipAction::Accept) => {
+                Some(FriendshipState::Friends)
+            }
+            (FriendshipState::PendingIncoming, FriendshipAction::Reject) => {
+                Some(FriendshipState::None)
+            }
+            (FriendshipState::Friends, FriendshipAction::Delete) => Some(FriendshipState::None),
+            _ => None,
+        }
+    }
+
+    /// Like [`Self::transition`], failing with [`SocialClientError::InvalidTransition`] if the
+    /// action isn't allowed on the friendship with the given address.
+    pub fn apply(self, action: FriendshipAction, address: &str) -> Result<Self, SocialClientError> {
+        self.transition(action)
+            .ok_or_else(|| SocialClientError::InvalidTransition {
+                action,
+                address: address.to_string(),
+                state: self,
+            })
+    }
+
+    /// The same friendship as seen by the other user.
+    pub fn mirrored(self) -> Self {
+        match self {
+            FriendshipState::PendingOutgoing => FriendshipState::PendingIncoming,
+            FriendshipState::PendingIncoming => FriendshipState::PendingOutgoing,
+            state => state,
+        }
+    }
+
+    /// The friendship with the given address according to the request events and friends of a user.
+    pub fn from_events(address: &str, request_events: &RequestEvents, friends: &[User]) -> Self {
+        let is_address = |user: &Option<User>| matches!(user, Some(user) if user.address.eq_ignore_ascii_case(address));
+        let has_request = |requests: &Option<Requests>| {
+            requests
+                .iter()
+                .flat_map(|requests| &requests.items)
+                .any(|request| is_address(&request.user))
+        };
+
+        if friends
+            .iter()
+            .any(|friend| friend.address.eq_ignore_ascii_case(address))
+        {
+            FriendshipState::Friends
+        } else if has_request(&request_events.outgoing) {
+            FriendshipState::PendingOutgoing
+        } else if has_request(&request_events.incoming) {
+            FriendshipState::PendingIncoming
+        } else {
+            FriendshipState::None
+        }
+    }
+}
+
+/// Get the friendship between the given user and the given address using the given module client.
+pub async fn get_friendship_state<T: Transport + 'static>(
+    module: &FriendshipsServiceClient<T>,
+    user: &AuthUser,
+    address: &str,
+) -> Result<FriendshipState, SocialClientError> {
+    let request_events = get_request_events(module, user).await?;
+    let friends = get_friends(module, user).await?;
+    Ok(FriendshipState::from_events(
+        address,
+        &request_events,
+        &friends,
+    ))
+}
+
+/// Check that the given user can perform the action on the friendship with the given address,
+/// returning the friendship expected once the action is applied.
+pub async fn validate_transition<T: Transport + 'static>(
+    module: &FriendshipsServiceClient<T>,
+    user: &AuthUser,
+    address: &str,
+    action: FriendshipAction,
+) -> Result<FriendshipState, SocialClientError> {
+    get_friendship_state(module, user, address)
+        .await?
+        .apply(action, address)
+}
+
+/// Wait until the friendship between the given user and the given address is the expected one.
+///
+/// Fails with [`SocialClientError::StateNotConverged`] if it isn't within the given timeout.
+pub async fn wait_for_friendship_state<T: Transport + 'static>(
+    module: &FriendshipsServiceClient<T>,
+    user: &AuthUser,
+    address: &str,
+    expected: FriendshipState,
+    timeout: Duration,
+) -> Result<(), SocialClientError> {
+    poll_friendship_state(address, expected, timeout, || {
+        get_friendship_state(module, user, address)
+    })
+    .await
+}
+
+/// Wait until the friendship between both users is the expected one as seen by `user`,
+/// and the mirrored one as seen by `other_user`.
+pub async fn wait_for_friendship<T: Transport + 'static>(
+    module: &FriendshipsServiceClient<T>,
+    user: &AuthUser,
+    other_module: &FriendshipsServiceClient<T>,
+    other_user: &AuthUser,
+    expected: FriendshipState,
+) -> Result<(), SocialClientError> {
+    wait_for_friendship_state(
+        module,
+        user,
+        &other_user.address,
+        expected,
+        CONVERGENCE_TIMEOUT,
+    )
+    .await?;
+    wait_for_friendship_state(
+        other_module,
+        other_user,
+        &user.address,
+        expected.mirrored(),
+        CONVERGENCE_TIMEOUT,
+    )
+    .await
+}
+
+/// Poll the friendship state with `fetch` until it's the expected one or the timeout elapses.
+pub(crate) async fn poll_friendship_state<F, Fut>(
+    address: &str,
+    expected: FriendshipState,
+    wait_timeout: Duration,
+    mut fetch: F,
+) -> Result<(), SocialClientError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<FriendshipState, SocialClientError>>,
+{
+    let deadline = Instant::now() + wait_timeout;
+    let mut observed = None;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        match timeout(remaining, fetch()).await {
+            Ok(Ok(state)) if state == expected => return Ok(()),
+            Ok(Ok(state)) => observed = Some(state),
+            Ok(Err(err)) => return Err(err),
+            Err(_) => {}
+        }
+
+        if Instant::now() + POLL_INTERVAL >= deadline {
+            return Err(SocialClientError::StateNotConverged {
+                address: address.to_string(),
+                expected,
+                observed,
+                timeout: wait_timeout,
+            });
+        }
+        sleep(POLL_INTERVAL).await;
+    }
+}
@@ -0,0 +1,76 @@
+use social_client::credentials::{
+    generate_credentials, write_credentials, GenerateOptions, HOMESERVER_VAR,
+};
+use std::{env, process::ExitCode};
+
+const DEFAULT_OUTPUT: &str = "credentials.generated.json";
+
+const USAGE: &str = "Usage: generate_identities <count> [--output <path>|-] [--homeserver <url>] [--login] [--login-url <url>]";
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let mut count = None;
+    let mut output = DEFAULT_OUTPUT.to_string();
+    let mut options = GenerateOptions {
+        homeserver: env::var(HOMESERVER_VAR).ok(),
+        ..Default::default()
+    };
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = match arg.as_str() {
+            "--output" | "--homeserver" | "--login-url" => match args.next() {
+                Some(value) => value,
+                None => {
+                    println!("Missing value for {arg}\n{USAGE}");
+                    return ExitCode::FAILURE;
+                }
+            },
+            _ => String::new(),
+        };
+        match arg.as_str() {
+            "--output" => output = value,
+            "--homeserver" => options.homeserver = Some(value),
+            "--login" => options.log_in = true,
+            "--login-url" => options.login_url = Some(value),
+            _ => match arg.parse::<usize>() {
+                Ok(value) if count.is_none() => count = Some(value),
+                _ => {
+                    println!("Unexpected argument {arg}\n{USAGE}");
+                    return ExitCode::FAILURE;
+                }
+            },
+        }
+    }
+    let Some(count) = count else {
+        println!("{USAGE}");
+        return ExitCode::FAILURE;
+    };
+    if options.log_in && options.homeserver.is_none() {
+        println!("--login needs a homeserver, given by --homeserver or {HOMESERVER_VAR}");
+        return ExitCode::FAILURE;
+    }
+
+    let credentials = match generate_credentials(count, &options).await {
+        Ok(credentials) => credentials,
+        Err(err) => {
+            println!("Failed to generate the users: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if output == "-" {
+        println!("{credentials:#}");
+        return ExitCode::SUCCESS;
+    }
+    match write_credentials(&output, &credentials) {
+        Ok(()) => {
+            println!("Generated {count} users in {output}");
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            println!("Failed to write {output}: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use std::{
+    fmt,
+    time::{Duration, SystemTime},
+};
+
+use k256::ecdsa::SigningKey;
+use serde_json::json;
+use sha3::{Digest, Keccak256};
+
+/// How long the ephemeral key of an auth chain is valid.
+pub const EPHEMERAL_KEY_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// An Ethereum account able to sign messages, identifying a Decentraland user by its address.
+#[derive(Clone)]
+pub struct Wallet {
+    key: SigningKey,
+}
+
+impl Wallet {
+    /// A new random account, e.g. for a throwaway test user.
+    pub fn random() -> Self {
+        Self {
+            key: SigningKey::random(&mut rand::thread_rng()),
+        }
+    }
+
+    /// The account of the given hex private key, with or without the `0x` prefix.
+    pub fn from_private_key(private_key: &str) -> Result<Self, IdentityError> {
+        let private_key = private_key.trim();
+        let bytes = hex::decode(private_key.strip_prefix("0x").unwrap_or(private_key))
+            .map_err(|err| IdentityError::InvalidKey(err.to_string()))?;
+        Self::from_bytes(&bytes)
+    }
+
+    /// The account of an encrypted JSON keystore file, as written by geth or MetaMask.
+    pub fn from_keystore(path: &str, password: &str) -> Result<Self, IdentityError> {
+        let bytes =
+            eth_keystore::decrypt_key(path, password).map_err(|err| IdentityError::Keystore {
+                path: path.to_string(),
+                message: err.to_string(),
+            })?;
+        Self::from_bytes(&bytes)
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, IdentityError> {
+        let key = SigningKey::from_slice(bytes)
+            .map_err(|err| IdentityError::InvalidKey(err.to_string()))?;
+        Ok(Self { key })
+    }
+
+    /// The hex private key, with the `0x` prefix.
+    pub fn private_key(&self) -> String {
+        format!("0x{}", hex::encode(self.key.to_bytes()))
+    }
+
+    /// The lowercase address, with the `0x` prefix.
+    pub fn address(&self) -> String {
+        let public_key = self.key.verifying_key().to_encoded_point(false);
+        // The address is the last 20 bytes of the hash of the public key without its 0x04 tag
+        let hash = Keccak256::digest(&public_key.as_bytes()[1..]);
+        format!("0x{}", hex::encode(&hash[12..]))
+    }
+
+    /// Sign a message the way `personal_sign` does, returning the hex `r || s || v` signature.
+    pub fn sign_message(&self, message: &str) -> Result<String, IdentityError> {
+        let mut hasher = Keccak256::new();
+        hasher.update(format!("\x19Ethereum Signed Message:\n{}", message.len()));
+        hasher.update(message);
+        let (signature, recovery_id) = self
+            .key
+            .sign_prehash_recoverable(&hasher.finalize())
+            .map_err(|err| IdentityError::Signing(err.to_string()))?;
+
+        let mut bytes = signature.to_bytes().to_vec();
+        bytes.push(27 + recovery_id.to_byte());
+        Ok(format!("0x{}", hex::encode(bytes)))
+    }
+
+    /// Delegate the signatures of this wallet to a new ephemeral key for the given time.
+    pub fn create_identity(&self, ttl: Duration) -> Result<Identity, IdentityError> {
+        let ephemeral = Wallet::random();
+        let expiration = humantime::format_rfc3339_millis(SystemTime::now() + ttl);
+        let ephemeral_message = format!(
+            "Decentraland Login\nEphemeral address: {}\nExpiration: {expiration}",
+            ephemeral.address()
+        );
+        let signature = self.sign_message(&ephemeral_message)?;
+        Ok(Identity {
+            ephemeral,
+            links: vec![
+                AuthLink {
+                    kind: AuthLinkType::Signer,
+                    payload: self.address(),
+                    signature: String::new(),
+                },
+                AuthLink {
+                    kind: AuthLinkType::EcdsaEphemeral,
+                    payload: ephemeral_message,
+                    signature,
+                },
+            ],
+        })
+    }
+}
+
+impl fmt::Debug for Wallet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Wallet")
+            .field("address", &self.address())
+            .finish_non_exhaustive()
+    }
+}
+
+/// The kind of a link of an auth chain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuthLinkType {
+    /// The address of the wallet, unsigned.
+    Signer,
+    /// The ephemeral key, signed by the wallet.
+    EcdsaEphemeral,
+    /// The signed payload, signed by the ephemeral key.
+    EcdsaSignedEntity,
+}
+
+impl fmt::Display for AuthLinkType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            AuthLinkType::Signer => "SIGNER",
+            AuthLinkType::EcdsaEphemeral => "ECDSA_EPHEMERAL",
+            AuthLinkType::EcdsaSignedEntity => "ECDSA_SIGNED_ENTITY",
+        };
+        f.write_str(kind)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuthLink {
+    pub kind: AuthLinkType,
+    pub payload: String,
+    pub signature: String,
+}
+
+/// A Decentraland auth chain proving that a wallet signed a payload through an ephemeral key.
+pub type AuthChain = Vec<AuthLink>;
+
+/// The JSON form of an auth chain, as expected by the Decentraland services.
+pub fn auth_chain_to_json(chain: &[AuthLink]) -> serde_json::Value {
+    chain
+        .iter()
+        .map(|link| {
+            json!({
+                "type": link.kind.to_string(),
+                "payload": link.payload,
+                "signature": link.signature,
+            })
+        })
+        .collect()
+}
+
+/// An ephemeral key the signatures of a wallet were delegated to.
+#[derive(Clone, Debug)]
+pub struct Identity {
+    ephemeral: Wallet,
+    links: Vec<AuthLink>,
+}
+
+impl Identity {
+    /// The address of the wallet that created the identity.
+    pub fn address(&self) -> &str {
+        &self.links[0].payload
+    }
+
+    /// Sign the given payload with the ephemeral key, returning the whole auth chain.
+    pub fn sign(&self, payload: &str) -> Result<AuthChain, IdentityError> {
+        let mut chain = self.links.clone();
+        chain.push(AuthLink {
+            kind: AuthLinkType::EcdsaSignedEntity,
+            payload: payload.to_string(),
+            signature: self.ephemeral.sign_message(payload)?,
+        });
+        Ok(chain)
+    }
+}
+
+/// Errors returned while loading a wallet or signing with it.
+#[derive(Debug)]
+pub enum IdentityError {
+    /// The private key is not a valid secp256k1 key.
+    InvalidKey(String),
+    /// The keystore could not be read or decrypted.
+    Keystore { path: String, message: String },
+    /// The message could not be signed.
+    Signing(String),
+}
+
+impl fmt::Display for IdentityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IdentityError::InvalidKey(message) => write!(f, "invalid private key: {message}"),
+            IdentityError::Keystore { path, message } => {
+                write!(f, "failed to decrypt keystore {path}: {message}")
+            }
+            IdentityError::Signing(message) => write!(f, "failed to sign: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for IdentityError {}
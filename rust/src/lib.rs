@@ -1,11 +1,43 @@
+pub mod cli;
+pub mod client;
+pub mod config;
 pub mod credentials;
+pub mod credentials_file;
+pub mod encryption;
+pub mod error;
+pub mod event_stream;
 pub mod friendship_procedures;
+pub mod friendship_state;
+pub mod identity;
+pub mod matrix;
+pub mod mock_server;
+pub mod proto_diff;
+pub mod protocol_selection;
+pub mod reconnect;
+pub mod secret;
+pub mod token;
 
-include!(concat!(
-    env!("OUT_DIR"),
-    "/decentraland.social.friendships.rs"
-));
-
-pub struct MyExampleContext {
-    pub hardcoded_database: Vec<User>,
+/// The friendships protocol at commit `c14d71c` of the protocol repository.
+#[cfg(feature = "protocol-v1")]
+pub mod v1 {
+    include!(concat!(
+        env!("OUT_DIR"),
+        "/v1/decentraland.social.friendships.rs"
+    ));
 }
+
+/// The version of the friendships protocol the rest of the crate and the binaries use, the one
+/// named by `SOCIAL_CLIENT_PROTOCOL` at build time or else the newest one enabled, see
+/// [`protocol_selection`], also re-exported from the crate root. They use its types directly, so a
+/// build uses a single version.
+#[cfg(protocol = "v1")]
+pub use v1 as protocol;
+
+/// The module name of the version re-exported as [`protocol`], e.g. `v1`.
+#[cfg(feature = "protocol-v1")]
+pub const PROTOCOL_VERSION: &str = env!("SOCIAL_CLIENT_SELECTED_PROTOCOL");
+
+pub use protocol::*;
+
+#[cfg(not(feature = "protocol-v1"))]
+compile_error!("a protocol version must be enabled, e.g. the `protocol-v1` feature");
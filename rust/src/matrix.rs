@@ -0,0 +1,425 @@
+use std::{
+    collections::HashMap,
+    fmt, io,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::json;
+use tokio::sync::Mutex;
+
+use crate::{
+    credentials::{AuthUser, TokenRefresher},
+    encryption::{decrypt, encrypt, is_encrypted},
+    identity::{auth_chain_to_json, IdentityError, Wallet, EPHEMERAL_KEY_TTL},
+    secret::Secret,
+};
+
+pub const LOGIN_PATH: &str = "/_matrix/client/v3/login";
+pub const REFRESH_PATH: &str = "/_matrix/client/v3/refresh";
+
+/// File caching the sessions by homeserver and user, in the working directory.
+pub const DEFAULT_SESSIONS_FILE: &str = ".matrix-sessions.json";
+
+/// Sessions expiring sooner than this are refreshed before being used.
+const EXPIRY_MARGIN: Duration = Duration::from_secs(30);
+
+/// An access token obtained from a Synapse homeserver.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MatrixSession {
+    /// The full Matrix user id, e.g. `@0xabc...:decentraland.zone`.
+    pub user_id: String,
+    pub access_token: Secret,
+    /// Only given by homeservers supporting refresh tokens.
+    pub refresh_token: Option<Secret>,
+    /// When the access token expires, in milliseconds since the Unix epoch.
+    pub expires_at: Option<u64>,
+}
+
+impl MatrixSession {
+    /// The address of the user, the localpart of its Matrix user id.
+    pub fn address(&self) -> &str {
+        let user_id = self.user_id.strip_prefix('@').unwrap_or(&self.user_id);
+        user_id.split(':').next().unwrap_or(user_id)
+    }
+
+    /// Whether the access token expired or is about to.
+    pub fn is_expired(&self) -> bool {
+        matches!(self.expires_at, Some(expires_at) if now_millis() + EXPIRY_MARGIN.as_millis() as u64 >= expires_at)
+    }
+
+    fn from_response(
+        response: &serde_json::Value,
+        user_id: Option<&str>,
+    ) -> Result<Self, MatrixError> {
+        let field = |name: &str| response[name].as_str().map(str::to_string);
+        let access_token = field("access_token").ok_or_else(|| {
+            MatrixError::InvalidResponse("the response has no access_token".to_string())
+        })?;
+        let user_id = field("user_id")
+            .or_else(|| user_id.map(str::to_string))
+            .ok_or_else(|| {
+                MatrixError::InvalidResponse("the response has no user_id".to_string())
+            })?;
+        Ok(Self {
+            user_id,
+            access_token: access_token.into(),
+            refresh_token: field("refresh_token").map(Secret::from),
+            expires_at: response["expires_in_ms"]
+                .as_u64()
+                .map(|expires_in| now_millis() + expires_in),
+        })
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        json!({
+            "user_id": self.user_id,
+            "access_token": self.access_token.expose(),
+            "refresh_token": self.refresh_token.as_ref().map(Secret::expose),
+            "expires_at": self.expires_at,
+        })
+    }
+
+    fn from_json(value: &serde_json::Value) -> Option<Self> {
+        Some(Self {
+            user_id: value["user_id"].as_str()?.to_string(),
+            access_token: value["access_token"].as_str()?.into(),
+            refresh_token: value["refresh_token"].as_str().map(Secret::from),
+            expires_at: value["expires_at"].as_u64(),
+        })
+    }
+}
+
+/// Errors returned while logging in against the homeserver.
+#[derive(Debug)]
+pub enum MatrixError {
+    /// The homeserver could not be reached.
+    Http(reqwest::Error),
+    /// The homeserver answered with a Matrix error, e.g. `M_FORBIDDEN` for a wrong password.
+    Rejected {
+        status: u16,
+        errcode: String,
+        message: String,
+    },
+    /// The homeserver answered with an unexpected body.
+    InvalidResponse(String),
+    /// The sessions file could not be written.
+    Cache { path: String, err: io::Error },
+    /// The auth chain of a wallet login could not be signed.
+    Identity(IdentityError),
+}
+
+impl fmt::Display for MatrixError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MatrixError::Http(err) => write!(f, "request to the homeserver failed: {err}"),
+            MatrixError::Rejected {
+                status,
+                errcode,
+                message,
+            } => write!(f, "homeserver answered {status} {errcode}: {message}"),
+            MatrixError::InvalidResponse(message) => {
+                write!(f, "invalid homeserver response: {message}")
+            }
+            MatrixError::Cache { path, err } => write!(f, "failed to write {path}: {err}"),
+            MatrixError::Identity(err) => write!(f, "failed to sign the login: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for MatrixError {}
+
+impl From<reqwest::Error> for MatrixError {
+    fn from(err: reqwest::Error) -> Self {
+        MatrixError::Http(err)
+    }
+}
+
+impl From<IdentityError> for MatrixError {
+    fn from(err: IdentityError) -> Self {
+        MatrixError::Identity(err)
+    }
+}
+
+/// How a [`MatrixLogin`] proves who the user is.
+enum LoginMethod {
+    /// `m.login.password`
+    Password(Secret),
+    /// `m.login.decentraland`, with an auth chain signing the login timestamp.
+    Wallet(Wallet),
+}
+
+/// Obtains access tokens by logging in against a Synapse-compatible homeserver, with a password
+/// or with an Ethereum wallet.
+///
+/// Sessions are cached in [`DEFAULT_SESSIONS_FILE`] so they are reused across runs, encrypted when
+/// given a passphrase, and are renewed with their refresh token, or by logging in again, once expired or rejected by the
+/// social service.
+pub struct MatrixLogin {
+    homeserver: String,
+    login_url: String,
+    user: String,
+    method: LoginMethod,
+    sessions_file: Option<String>,
+    sessions_passphrase: Option<Secret>,
+    session: Mutex<Option<MatrixSession>>,
+    http: reqwest::Client,
+}
+
+impl MatrixLogin {
+    /// `user` is the Matrix user id or its localpart, i.e. the address of the user.
+    pub fn new(homeserver: &str, user: &str, password: &str) -> Self {
+        Self::with_method(
+            homeserver,
+            user.to_string(),
+            LoginMethod::Password(password.into()),
+        )
+    }
+
+    /// Log in as the address of the wallet with a Decentraland auth chain, signed by a new
+    /// ephemeral key on every login.
+    pub fn with_wallet(homeserver: &str, wallet: Wallet) -> Self {
+        Self::with_method(homeserver, wallet.address(), LoginMethod::Wallet(wallet))
+    }
+
+    fn with_method(homeserver: &str, user: String, method: LoginMethod) -> Self {
+        let homeserver = homeserver.trim_end_matches('/').to_string();
+        Self {
+            login_url: format!("{homeserver}{LOGIN_PATH}"),
+            homeserver,
+            user,
+            method,
+            sessions_file: Some(DEFAULT_SESSIONS_FILE.to_string()),
+            sessions_passphrase: None,
+            session: Mutex::new(None),
+            http: reqwest::Client::new(),
+        }
+    }
+
+    /// Log in through the given URL instead of the `/_matrix/client/v3/login` endpoint of the
+    /// homeserver, e.g. a gateway in front of it.
+    pub fn with_login_url(mut self, login_url: &str) -> Self {
+        self.login_url = login_url.to_string();
+        self
+    }
+
+    /// Cache the sessions in the given file, or nowhere if `None`.
+    pub fn with_sessions_file(mut self, path: Option<&str>) -> Self {
+        self.sessions_file = path.map(str::to_string);
+        self
+    }
+
+    /// Encrypt the cached sessions with the given passphrase, like an encrypted credentials file,
+    /// or write them in plain text if `None`. A cache written otherwise is ignored and replaced.
+    pub fn with_sessions_passphrase(mut self, passphrase: Option<&str>) -> Self {
+        self.sessions_passphrase = passphrase.map(Secret::from);
+        self
+    }
+
+    /// The current session, taken from the cache if it hasn't expired, otherwise refreshed or
+    /// obtained by logging in.
+    pub async fn session(&self) -> Result<MatrixSession, MatrixError> {
+        let mut session = self.session.lock().await;
+        if session.is_none() {
+            *session = self.read_cached_session();
+        }
+        match session.as_ref() {
+            Some(cached) if !cached.is_expired() => Ok(cached.clone()),
+            cached => {
+                let renewed = self.renew(cached).await?;
+                *session = Some(renewed.clone());
+                Ok(renewed)
+            }
+        }
+    }
+
+    /// Replace the current session, even if it hasn't expired.
+    pub async fn refresh(&self) -> Result<MatrixSession, MatrixError> {
+        let mut session = self.session.lock().await;
+        let renewed = self.renew(session.as_ref()).await?;
+        *session = Some(renewed.clone());
+        Ok(renewed)
+    }
+
+    /// The user authenticated with the current session, refreshing its token through this login
+    /// when it's rejected by the social service.
+    pub async fn auth_user(self: &Arc<Self>) -> Result<AuthUser, MatrixError> {
+        let session = self.session().await?;
+        Ok(
+            AuthUser::new(session.address(), session.access_token.expose())
+                .with_token_refresher(self.clone()),
+        )
+    }
+
+    /// Refresh the given session with its refresh token, falling back to logging in.
+    async fn renew(&self, session: Option<&MatrixSession>) -> Result<MatrixSession, MatrixError> {
+        let refreshed = match session {
+            Some(
+                session @ MatrixSession {
+                    refresh_token: Some(refresh_token),
+                    ..
+                },
+            ) => match self.refresh_session(session, refresh_token).await {
+                Ok(session) => Some(session),
+                Err(err) => {
+                    log::warn!("Failed to refresh the session of {}: {err}", self.user);
+                    None
+                }
+            },
+            _ => None,
+        };
+        let session = match refreshed {
+            Some(session) => session,
+            None => self.login().await?,
+        };
+        self.write_cached_session(&session)?;
+        Ok(session)
+    }
+
+    async fn login(&self) -> Result<MatrixSession, MatrixError> {
+        let identifier = json!({ "type": "m.id.user", "user": self.user });
+        let body = match &self.method {
+            LoginMethod::Password(password) => json!({
+                "type": "m.login.password",
+                "identifier": identifier,
+                "password": password.expose(),
+                "refresh_token": true,
+            }),
+            LoginMethod::Wallet(wallet) => {
+                let timestamp = now_millis().to_string();
+                let auth_chain = wallet
+                    .create_identity(EPHEMERAL_KEY_TTL)?
+                    .sign(&timestamp)?;
+                json!({
+                    "type": "m.login.decentraland",
+                    "identifier": identifier,
+                    "timestamp": timestamp,
+                    "auth_chain": auth_chain_to_json(&auth_chain),
+                    "refresh_token": true,
+                })
+            }
+        };
+        let response = self.post(&self.login_url, &body).await?;
+        MatrixSession::from_response(&response, None)
+    }
+
+    async fn refresh_session(
+        &self,
+        session: &MatrixSession,
+        refresh_token: &Secret,
+    ) -> Result<MatrixSession, MatrixError> {
+        let body = json!({ "refresh_token": refresh_token.expose() });
+        let url = format!("{}{REFRESH_PATH}", self.homeserver);
+        let response = self.post(&url, &body).await?;
+        let mut refreshed = MatrixSession::from_response(&response, Some(&session.user_id))?;
+        // The refresh token is only rotated by some homeservers
+        if refreshed.refresh_token.is_none() {
+            refreshed.refresh_token = Some(refresh_token.clone());
+        }
+        Ok(refreshed)
+    }
+
+    async fn post(
+        &self,
+        url: &str,
+        body: &serde_json::Value,
+    ) -> Result<serde_json::Value, MatrixError> {
+        let response = self.http.post(url).json(body).send().await?;
+        let status = response.status();
+        let content = response.text().await?;
+        let body = serde_json::from_str::<serde_json::Value>(&content);
+        if !status.is_success() {
+            // Proxies in front of the homeserver answer errors like a 502 with an HTML page
+            let body = body.unwrap_or_default();
+            let message = match body["error"].as_str() {
+                Some(message) => message.to_string(),
+                None => status.canonical_reason().unwrap_or_default().to_string(),
+            };
+            return Err(MatrixError::Rejected {
+                status: status.as_u16(),
+                errcode: body["errcode"].as_str().unwrap_or("M_UNKNOWN").to_string(),
+                message,
+            });
+        }
+        body.map_err(|err| MatrixError::InvalidResponse(err.to_string()))
+    }
+
+    fn cache_key(&self) -> String {
+        format!("{} {}", self.homeserver, self.user)
+    }
+
+    fn read_cached_session(&self) -> Option<MatrixSession> {
+        let sessions = read_sessions(
+            self.sessions_file.as_deref()?,
+            self.sessions_passphrase.as_ref(),
+        );
+        MatrixSession::from_json(sessions.get(&self.cache_key())?)
+    }
+
+    fn write_cached_session(&self, session: &MatrixSession) -> Result<(), MatrixError> {
+        let Some(path) = &self.sessions_file else {
+            return Ok(());
+        };
+        let cache_error = |err| MatrixError::Cache {
+            path: path.clone(),
+            err,
+        };
+        let mut sessions = read_sessions(path, self.sessions_passphrase.as_ref());
+        sessions.insert(self.cache_key(), session.to_json());
+        let mut content = serde_json::to_string_pretty(&sessions)
+            .map_err(|err| MatrixError::InvalidResponse(err.to_string()))?;
+        if let Some(passphrase) = &self.sessions_passphrase {
+            let encrypted = encrypt(&content, passphrase.expose())
+                .map_err(|err| cache_error(io::Error::other(err)))?;
+            content = serde_json::to_string_pretty(&encrypted)
+                .map_err(|err| MatrixError::InvalidResponse(err.to_string()))?;
+        }
+        write_private(path, &content).map_err(cache_error)
+    }
+}
+
+#[async_trait::async_trait]
+impl TokenRefresher for MatrixLogin {
+    async fn refresh_token(
+        &self,
+        _user: &AuthUser,
+    ) -> Result<Secret, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(self.refresh().await?.access_token)
+    }
+}
+
+/// The cached sessions, decrypted with the passphrase if given. An unreadable file, or one not
+/// encrypted the same way, is treated as an empty cache.
+fn read_sessions(path: &str, passphrase: Option<&Secret>) -> HashMap<String, serde_json::Value> {
+    let Some(cache) = std::fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str::<serde_json::Value>(&content).ok())
+    else {
+        return HashMap::new();
+    };
+    let sessions = match (passphrase, is_encrypted(&cache)) {
+        (Some(passphrase), true) => decrypt(&cache, passphrase.expose())
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok()),
+        (None, false) => serde_json::from_value(cache).ok(),
+        // Sessions in plain text are replaced by encrypted ones, and the other way around
+        _ => None,
+    };
+    sessions.unwrap_or_default()
+}
+
+/// Write a file only readable by the current user, as it holds access tokens.
+pub(crate) fn write_private(path: &str, content: &str) -> io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    io::Write::write_all(&mut options.open(path)?, content.as_bytes())
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
@@ -0,0 +1,561 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use dcl_rpc::{
+    rpc_protocol::RemoteErrorResponse,
+    server::{RpcServer, ServerEventsSender},
+    service_module_definition::ProcedureContext,
+    stream_protocol::GeneratorYielder,
+    transports::{
+        memory::MemoryTransport,
+        web_sockets::{tungstenite::TungsteniteWebSocket, WebSocketTransport},
+    },
+};
+use tokio::{net::TcpListener, sync::Mutex, task::JoinHandle};
+use tokio_tungstenite::{accept_async, MaybeTlsStream};
+
+use crate::{
+    client::{SocialClient, Transport, FRIENDSHIPS_PORT},
+    credentials::AuthUser,
+    error::SocialClientError,
+    friendship_event_payload, friendship_event_response,
+    friendship_state::{FriendshipAction, FriendshipState},
+    request_events_response, subscribe_friendship_events_updates_response,
+    update_friendship_response, users_response, AcceptResponse, BadRequestError, CancelResponse,
+    DeleteResponse, FriendshipEventResponse, FriendshipEventResponses,
+    FriendshipsServiceRegistration, FriendshipsServiceServer, MutualFriendsPayload, Payload,
+    RejectResponse, RequestEvents, RequestEventsResponse, RequestResponse, Requests,
+    ServerStreamResponse, SubscribeFriendshipEventsUpdatesResponse, UnauthorizedError,
+    UpdateFriendshipPayload, UpdateFriendshipResponse, User, Users, UsersResponse,
+};
+
+/// An in-process social service keeping friendships in memory, for testing without a running
+/// social service and Synapse.
+///
+/// Every client connected to the same server shares the same friendships, either through
+/// [`Self::connect`] (in memory) or through the WebSocket hosts it listens on, see [`Self::listen`].
+/// Updates are applied immediately and rejected with a `BadRequestError` when they aren't a
+/// valid [`FriendshipState`] transition.
+pub struct MockSocialServer {
+    context: MockContext,
+    memory_transports: ServerEventsSender<MemoryTransport>,
+    hosts: Vec<String>,
+    ws_connections: Arc<Mutex<Vec<Arc<Transport>>>>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl MockSocialServer {
+    /// Start a server reachable in memory, authenticating the given users by their token.
+    pub fn start(users: &[AuthUser]) -> Self {
+        let context = MockContext::new(users);
+        let server = RpcServer::<MockContext, MemoryTransport>::create(context.clone());
+        let (memory_transports, task) = run(server);
+        Self {
+            context,
+            memory_transports,
+            hosts: vec![],
+            ws_connections: Arc::default(),
+            tasks: vec![task],
+        }
+    }
+
+    /// Like [`Self::start`], also accepting WebSocket connections on the given address,
+    /// e.g. `127.0.0.1:8085`, or `127.0.0.1:0` for a free port, see [`Self::host`].
+    pub async fn listen(address: &str, users: &[AuthUser]) -> Result<Self, std::io::Error> {
+        let mut server = Self::start(users);
+        server.add_listener(address).await?;
+        Ok(server)
+    }
+
+    /// Also accept WebSocket connections on the given address, sharing the friendships with the
+    /// other connections. The host is the bound address, so with port 0 it listens on a free one.
+    pub async fn add_listener(&mut self, address: &str) -> Result<(), std::io::Error> {
+        let listener = TcpListener::bind(address).await?;
+        let bound = listener.local_addr()?;
+        let server = RpcServer::<MockContext, Transport>::create(self.context.clone());
+        let (ws_transports, ws_task) = run(server);
+        let accepted = self.ws_connections.clone();
+        // The listener stops accepting connections when the task is aborted
+        let listener_task = tokio::spawn(async move {
+            loop {
+                let Ok((stream, _)) = listener.accept().await else {
+                    continue;
+                };
+                let Ok(connection) = accept_async(MaybeTlsStream::Plain(stream)).await else {
+                    continue;
+                };
+                let transport = Arc::new(WebSocketTransport::new(Arc::new(
+                    TungsteniteWebSocket::new(connection),
+                )));
+                accepted.lock().await.push(transport.clone());
+                if ws_transports.send_attach_transport(transport).is_err() {
+                    break;
+                }
+            }
+        });
+
+        self.hosts.push(format!("ws://{bound}"));
+        self.tasks.extend([ws_task, listener_task]);
+        Ok(())
+    }
+
+    /// The first WebSocket host to connect to, if the server listens on any.
+    pub fn host(&self) -> Option<&str> {
+        self.hosts.first().map(String::as_str)
+    }
+
+    /// Every WebSocket host the server listens on.
+    pub fn hosts(&self) -> &[String] {
+        &self.hosts
+    }
+
+    /// Close the WebSocket connections accepted so far, as if the network dropped them, while
+    /// still accepting new ones.
+    pub async fn drop_connections(&self) {
+        for transport in self.ws_connections.lock().await.drain(..) {
+            dcl_rpc::transports::Transport::close(&*transport).await;
+        }
+    }
+
+    /// Connect a new in-memory client for the given user.
+    ///
+    /// Use a current-thread runtime (the default of `#[tokio::test]`): dcl-rpc registers the
+    /// listener of a response after sending the request, so with a multi-threaded runtime the
+    /// in-memory response may arrive first and the call never completes.
+    pub async fn connect(
+        &self,
+        user: AuthUser,
+    ) -> Result<SocialClient<MemoryTransport>, SocialClientError> {
+        let (client_transport, server_transport) = MemoryTransport::create();
+        self.memory_transports
+            .send_attach_transport(Arc::new(server_transport))
+            .map_err(|err| SocialClientError::Connection(format!("{err:?}")))?;
+        SocialClient::from_transport(client_transport, user).await
+    }
+}
+
+impl Drop for MockSocialServer {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+/// Register the mock service on every port and run the server in a background task.
+fn run<T: dcl_rpc::transports::Transport + 'static>(
+    mut server: RpcServer<MockContext, T>,
+) -> (ServerEventsSender<T>, JoinHandle<()>) {
+    server.set_module_registrator_handler(|port| {
+        if port.name == FRIENDSHIPS_PORT {
+            FriendshipsServiceRegistration::register_service(port, MockFriendshipsService);
+        }
+    });
+    let events = server.get_server_events_sender();
+    let task = tokio::spawn(async move { server.run().await });
+    (events, task)
+}
+
+/// The state shared by every connection to a [`MockSocialServer`].
+#[derive(Clone)]
+pub struct MockContext {
+    /// Lowercase address of each user, by synapse token.
+    addresses: Arc<HashMap<String, String>>,
+    database: Arc<Mutex<Database>>,
+}
+
+impl MockContext {
+    pub fn new(users: &[AuthUser]) -> Self {
+        let addresses = users
+            .iter()
+            .map(|user| (user.token.expose().to_string(), user.address.to_lowercase()))
+            .collect();
+        Self {
+            addresses: Arc::new(addresses),
+            database: Arc::new(Mutex::new(Database::default())),
+        }
+    }
+
+    fn authenticate(&self, payload: &Option<Payload>) -> Result<String, UnauthorizedError> {
+        payload
+            .as_ref()
+            .and_then(|payload| payload.synapse_token.as_ref())
+            .and_then(|token| self.addresses.get(token))
+            .cloned()
+            .ok_or_else(|| UnauthorizedError {
+                message: "invalid synapse token".to_string(),
+            })
+    }
+}
+
+/// Friendships by lowercase address.
+#[derive(Default)]
+struct Database {
+    /// Pending requests by sender and receiver.
+    requests: HashMap<(String, String), Request>,
+    /// Friendships as a pair of addresses, the lowest first.
+    friendships: HashSet<(String, String)>,
+    subscribers: HashMap<String, Vec<GeneratorYielder<SubscribeFriendshipEventsUpdatesResponse>>>,
+}
+
+struct Request {
+    created_at: i64,
+    message: Option<String>,
+}
+
+impl Database {
+    fn state(&self, address: &str, other: &str) -> FriendshipState {
+        if self.friendships.contains(&pair(address, other)) {
+            FriendshipState::Friends
+        } else if self
+            .requests
+            .contains_key(&(address.to_string(), other.to_string()))
+        {
+            FriendshipState::PendingOutgoing
+        } else if self
+            .requests
+            .contains_key(&(other.to_string(), address.to_string()))
+        {
+            FriendshipState::PendingIncoming
+        } else {
+            FriendshipState::None
+        }
+    }
+
+    fn friends(&self, address: &str) -> Vec<String> {
+        self.friendships
+            .iter()
+            .filter_map(|(a, b)| match (a == address, b == address) {
+                (true, _) => Some(b.clone()),
+                (_, true) => Some(a.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn request_events(&self, address: &str) -> RequestEvents {
+        let mut outgoing = vec![];
+        let mut incoming = vec![];
+        for ((from, to), request) in &self.requests {
+            if from == address {
+                outgoing.push(request.response(to));
+            } else if to == address {
+                incoming.push(request.response(from));
+            }
+        }
+        RequestEvents {
+            outgoing: Some(requests(outgoing)),
+            incoming: Some(requests(incoming)),
+        }
+    }
+
+    /// Apply the update sent by `address`, returning the event as seen by each user.
+    fn update(
+        &mut self,
+        address: &str,
+        body: friendship_event_payload::Body,
+    ) -> Result<(String, FriendshipEventResponse, FriendshipEventResponse), BadRequestError> {
+        let (action, other) = FriendshipAction::from_body(&body);
+        let other = other
+            .map(str::to_lowercase)
+            .ok_or_else(|| bad_request("missing user"))?;
+        if other == address {
+            return Err(bad_request("cannot update a friendship with yourself"));
+        }
+        self.state(address, &other)
+            .apply(action, &other)
+            .map_err(|err| bad_request(&err.to_string()))?;
+
+        let sender = (address.to_string(), other.clone());
+        let receiver = (other.clone(), address.to_string());
+        let (own, others) = match body {
+            friendship_event_payload::Body::Request(request) => {
+                let request = Request {
+                    created_at: now(),
+                    message: request.message,
+                };
+                let own = friendship_event_response::Body::Request(request.response(&other));
+                let others = friendship_event_response::Body::Request(request.response(address));
+                self.requests.insert(sender, request);
+                (own, others)
+            }
+            friendship_event_payload::Body::Cancel(_) => {
+                self.requests.remove(&sender);
+                (
+                    friendship_event_response::Body::Cancel(CancelResponse { user: user(&other) }),
+                    friendship_event_response::Body::Cancel(CancelResponse {
+                        user: user(address),
+                    }),
+                )
+            }
+            friendship_event_payload::Body::Accept(_) => {
+                self.requests.remove(&receiver);
+                self.friendships.insert(pair(address, &other));
+                (
+                    friendship_event_response::Body::Accept(AcceptResponse { user: user(&other) }),
+                    friendship_event_response::Body::Accept(AcceptResponse {
+                        user: user(address),
+                    }),
+                )
+            }
+            friendship_event_payload::Body::Reject(_) => {
+                self.requests.remove(&receiver);
+                (
+                    friendship_event_response::Body::Reject(RejectResponse { user: user(&other) }),
+                    friendship_event_response::Body::Reject(RejectResponse {
+                        user: user(address),
+                    }),
+                )
+            }
+            friendship_event_payload::Body::Delete(_) => {
+                self.friendships.remove(&pair(address, &other));
+                (
+                    friendship_event_response::Body::Delete(DeleteResponse { user: user(&other) }),
+                    friendship_event_response::Body::Delete(DeleteResponse {
+                        user: user(address),
+                    }),
+                )
+            }
+        };
+        Ok((
+            other,
+            FriendshipEventResponse { body: Some(own) },
+            FriendshipEventResponse { body: Some(others) },
+        ))
+    }
+
+    /// Send the event to every subscription of the given address, dropping the closed ones.
+    async fn notify(&mut self, address: &str, event: FriendshipEventResponse) {
+        let Some(subscribers) = self.subscribers.get_mut(address) else {
+            return;
+        };
+        let update = SubscribeFriendshipEventsUpdatesResponse {
+            response: Some(
+                subscribe_friendship_events_updates_response::Response::Events(
+                    FriendshipEventResponses {
+                        responses: vec![event],
+                    },
+                ),
+            ),
+        };
+        let mut open = vec![];
+        for subscriber in subscribers.drain(..) {
+            if subscriber.r#yield(update.clone()).await.is_ok() {
+                open.push(subscriber);
+            }
+        }
+        *subscribers = open;
+    }
+}
+
+impl Request {
+    fn response(&self, address: &str) -> RequestResponse {
+        RequestResponse {
+            user: user(address),
+            created_at: self.created_at,
+            message: self.message.clone(),
+        }
+    }
+}
+
+/// Errors are always answered in the response of each procedure, so the procedures never fail.
+pub enum MockServerError {}
+
+impl RemoteErrorResponse for MockServerError {
+    fn error_code(&self) -> u32 {
+        match *self {}
+    }
+
+    fn error_message(&self) -> String {
+        match *self {}
+    }
+}
+
+/// The `FriendshipsService` implementation of a [`MockSocialServer`].
+pub struct MockFriendshipsService;
+
+#[async_trait::async_trait]
+impl FriendshipsServiceServer<MockContext, MockServerError> for MockFriendshipsService {
+    async fn get_friends(
+        &self,
+        request: Payload,
+        context: ProcedureContext<MockContext>,
+    ) -> Result<ServerStreamResponse<UsersResponse>, MockServerError> {
+        let context = context.server_context;
+        let response = match context.authenticate(&Some(request)) {
+            Ok(address) => {
+                let friends = context.database.lock().await.friends(&address);
+                users_response::Response::Users(users(friends))
+            }
+            Err(err) => users_response::Response::UnauthorizedError(err),
+        };
+        Ok(stream(UsersResponse {
+            response: Some(response),
+        })
+        .await)
+    }
+
+    async fn get_mutual_friends(
+        &self,
+        request: MutualFriendsPayload,
+        context: ProcedureContext<MockContext>,
+    ) -> Result<ServerStreamResponse<UsersResponse>, MockServerError> {
+        let context = context.server_context;
+        let response = match context.authenticate(&request.auth_token) {
+            Ok(address) => {
+                let other = request
+                    .user
+                    .map(|user| user.address.to_lowercase())
+                    .unwrap_or_default();
+                let database = context.database.lock().await;
+                let other_friends: HashSet<String> = database.friends(&other).into_iter().collect();
+                let mutual_friends = database
+                    .friends(&address)
+                    .into_iter()
+                    .filter(|friend| other_friends.contains(friend))
+                    .collect();
+                users_response::Response::Users(users(mutual_friends))
+            }
+            Err(err) => users_response::Response::UnauthorizedError(err),
+        };
+        Ok(stream(UsersResponse {
+            response: Some(response),
+        })
+        .await)
+    }
+
+    async fn get_request_events(
+        &self,
+        request: Payload,
+        context: ProcedureContext<MockContext>,
+    ) -> Result<RequestEventsResponse, MockServerError> {
+        let context = context.server_context;
+        let response = match context.authenticate(&Some(request)) {
+            Ok(address) => request_events_response::Response::Events(
+                context.database.lock().await.request_events(&address),
+            ),
+            Err(err) => request_events_response::Response::UnauthorizedError(err),
+        };
+        Ok(RequestEventsResponse {
+            response: Some(response),
+        })
+    }
+
+    async fn update_friendship_event(
+        &self,
+        request: UpdateFriendshipPayload,
+        context: ProcedureContext<MockContext>,
+    ) -> Result<UpdateFriendshipResponse, MockServerError> {
+        let context = context.server_context;
+        let address = match context.authenticate(&request.auth_token) {
+            Ok(address) => address,
+            Err(err) => {
+                return Ok(UpdateFriendshipResponse {
+                    response: Some(update_friendship_response::Response::UnauthorizedError(err)),
+                })
+            }
+        };
+        let Some(body) = request.event.and_then(|event| event.body) else {
+            return Ok(UpdateFriendshipResponse {
+                response: Some(update_friendship_response::Response::BadRequestError(
+                    bad_request("missing event"),
+                )),
+            });
+        };
+
+        let mut database = context.database.lock().await;
+        let response = match database.update(&address, body) {
+            Ok((other, own, others)) => {
+                database.notify(&other, others).await;
+                update_friendship_response::Response::Event(own)
+            }
+            Err(err) => update_friendship_response::Response::BadRequestError(err),
+        };
+        Ok(UpdateFriendshipResponse {
+            response: Some(response),
+        })
+    }
+
+    async fn subscribe_friendship_events_updates(
+        &self,
+        request: Payload,
+        context: ProcedureContext<MockContext>,
+    ) -> Result<ServerStreamResponse<SubscribeFriendshipEventsUpdatesResponse>, MockServerError>
+    {
+        let context = context.server_context;
+        match context.authenticate(&Some(request)) {
+            Ok(address) => {
+                let (generator, yielder) = ServerStreamResponse::create();
+                context
+                    .database
+                    .lock()
+                    .await
+                    .subscribers
+                    .entry(address)
+                    .or_default()
+                    .push(yielder);
+                Ok(generator)
+            }
+            Err(err) => Ok(stream(SubscribeFriendshipEventsUpdatesResponse {
+                response: Some(
+                    subscribe_friendship_events_updates_response::Response::UnauthorizedError(err),
+                ),
+            })
+            .await),
+        }
+    }
+}
+
+/// A stream with a single item.
+async fn stream<M: Send + Sync + 'static>(item: M) -> ServerStreamResponse<M> {
+    let (generator, yielder) = ServerStreamResponse::create();
+    // The stream is unbounded, so it can only fail if the generator was dropped
+    let _ = yielder.r#yield(item).await;
+    generator
+}
+
+fn pair(a: &str, b: &str) -> (String, String) {
+    if a <= b {
+        (a.to_string(), b.to_string())
+    } else {
+        (b.to_string(), a.to_string())
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+fn user(address: &str) -> Option<User> {
+    Some(User {
+        address: address.to_string(),
+    })
+}
+
+fn users(addresses: Vec<String>) -> Users {
+    Users {
+        users: addresses
+            .into_iter()
+            .map(|address| User { address })
+            .collect(),
+    }
+}
+
+fn requests(items: Vec<RequestResponse>) -> Requests {
+    Requests {
+        total: items.len() as i64,
+        items,
+    }
+}
+
+fn bad_request(message: &str) -> BadRequestError {
+    BadRequestError {
+        message: message.to_string(),
+    }
+}
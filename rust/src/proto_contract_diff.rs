@@ -0,0 +1,52 @@
+use social_client::proto_diff::ProtoContract;
+use std::{env, process};
+
+const USAGE: &str = "Usage: proto_contract_diff <old.proto> <new.proto>";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let [old_path, new_path] = &args[..] else {
+        println!("{USAGE}");
+        return;
+    };
+    let (old, new) = match (read_contract(old_path), read_contract(new_path)) {
+        (Ok(old), Ok(new)) => (old, new),
+        (Err(err), _) | (_, Err(err)) => {
+            println!("{err}");
+            process::exit(2);
+        }
+    };
+
+    let changes = old.diff(&new);
+    if changes.is_empty() {
+        println!("No contract changes from {old_path} to {new_path}");
+        return;
+    }
+    println!("Contract changes from {old_path} to {new_path}:");
+    for change in &changes {
+        if change.is_wire_incompatible() {
+            println!("  {change} [wire-incompatible]");
+        } else {
+            println!("  {change}");
+        }
+    }
+
+    let incompatible = changes
+        .iter()
+        .filter(|change| change.is_wire_incompatible())
+        .count();
+    println!(
+        "{} changes, {incompatible} wire-incompatible",
+        changes.len()
+    );
+    // Lets CI fail on a bump breaking the contract
+    if incompatible > 0 {
+        process::exit(1);
+    }
+}
+
+fn read_contract(path: &str) -> Result<ProtoContract, String> {
+    let content =
+        std::fs::read_to_string(path).map_err(|err| format!("Failed to read {path}: {err}"))?;
+    ProtoContract::parse(&content).map_err(|err| format!("Failed to parse {path}: {err}"))
+}
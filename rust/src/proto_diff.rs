@@ -0,0 +1,1027 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+/// The contract declared by a `.proto` file: what can change between two versions of it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProtoContract {
+    pub package: Option<String>,
+    /// Messages by name, nested ones qualified with their parent like `Outer.Inner`.
+    pub messages: BTreeMap<String, Message>,
+    /// Enums by name, qualified like messages.
+    pub enums: BTreeMap<String, Enum>,
+    pub services: BTreeMap<String, Service>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Message {
+    pub fields: Vec<Field>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Field {
+    pub name: String,
+    pub number: u32,
+    pub label: Label,
+    /// The type as written, e.g. `string`, `User` or `map<string, User>`.
+    pub kind: String,
+    /// The oneof the field is part of.
+    pub oneof: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Label {
+    Singular,
+    Optional,
+    Repeated,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Enum {
+    pub values: Vec<(String, i32)>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Service {
+    pub rpcs: Vec<Rpc>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rpc {
+    pub name: String,
+    pub request: String,
+    pub request_stream: bool,
+    pub response: String,
+    pub response_stream: bool,
+}
+
+/// A `.proto` file that couldn't be parsed.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ProtoParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ProtoParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ProtoParseError {}
+
+/// The kind of element of a contract a [`Change`] is about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ElementKind {
+    Package,
+    Service,
+    Rpc,
+    Message,
+    Field,
+    Enum,
+    EnumValue,
+}
+
+impl fmt::Display for ElementKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            ElementKind::Package => "package",
+            ElementKind::Service => "service",
+            ElementKind::Rpc => "rpc",
+            ElementKind::Message => "message",
+            ElementKind::Field => "field",
+            ElementKind::Enum => "enum",
+            ElementKind::EnumValue => "enum value",
+        };
+        f.write_str(kind)
+    }
+}
+
+/// A difference between two versions of a contract. Elements are described with their qualified
+/// name and definition, e.g. `User.address = 1 (string)`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change {
+    Added {
+        kind: ElementKind,
+        element: String,
+    },
+    Removed {
+        kind: ElementKind,
+        element: String,
+        incompatible: bool,
+    },
+    /// Matched by definition, e.g. a field by its number or an rpc by its signature.
+    Renamed {
+        kind: ElementKind,
+        from: String,
+        to: String,
+        incompatible: bool,
+    },
+    Changed {
+        kind: ElementKind,
+        element: String,
+        from: String,
+        to: String,
+        incompatible: bool,
+    },
+}
+
+impl Change {
+    /// Whether peers built from the two versions can't understand each other anymore, e.g. a
+    /// field changed number or an rpc was removed.
+    pub fn is_wire_incompatible(&self) -> bool {
+        match self {
+            Change::Added { .. } => false,
+            Change::Removed { incompatible, .. }
+            | Change::Renamed { incompatible, .. }
+            | Change::Changed { incompatible, .. } => *incompatible,
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Added { kind, element } => write!(f, "added {kind} {element}"),
+            Change::Removed { kind, element, .. } => write!(f, "removed {kind} {element}"),
+            Change::Renamed { kind, from, to, .. } => write!(f, "renamed {kind} {from} to {to}"),
+            Change::Changed {
+                kind,
+                element,
+                from,
+                to,
+                ..
+            } => write!(f, "changed {kind} {element}: {from} -> {to}"),
+        }
+    }
+}
+
+impl ProtoContract {
+    /// Parse the messages, enums and services of a proto3 file, ignoring options and comments.
+    pub fn parse(content: &str) -> Result<Self, ProtoParseError> {
+        let mut parser = Parser {
+            tokens: tokenize(content)?,
+            position: 0,
+        };
+        let mut contract = ProtoContract::default();
+        while let Some(token) = parser.next() {
+            match token.text.as_str() {
+                "package" => {
+                    contract.package = Some(parser.expect_ident()?);
+                    parser.expect(";")?;
+                }
+                "message" => parser.message("", &mut contract)?,
+                "enum" => parser.enumeration("", &mut contract)?,
+                "service" => parser.service(&mut contract)?,
+                "extend" => parser.skip_block()?,
+                "syntax" | "import" | "option" => parser.skip_statement()?,
+                ";" => {}
+                other => return Err(token.error(format!("unexpected `{other}`"))),
+            }
+        }
+        Ok(contract)
+    }
+
+    /// The changes from this version of the contract to the given one.
+    pub fn diff(&self, new: &ProtoContract) -> Vec<Change> {
+        let mut changes = Vec::new();
+        if self.package != new.package {
+            changes.push(Change::Changed {
+                kind: ElementKind::Package,
+                element: "name".to_string(),
+                from: self.package.clone().unwrap_or_default(),
+                to: new.package.clone().unwrap_or_default(),
+                incompatible: true,
+            });
+        }
+
+        let renamed_messages = self.diff_messages(new, &mut changes);
+        let renamed_enums = self.diff_enums(new, &mut changes);
+        let types = TypeMapping {
+            old: self,
+            new,
+            renamed: renamed_messages.into_iter().chain(renamed_enums).collect(),
+        };
+
+        for (name, message) in &self.messages {
+            let new_name = types.renamed.get(name).unwrap_or(name);
+            if let Some(new_message) = new.messages.get(new_name) {
+                let names = (name.as_str(), new_name.as_str());
+                diff_fields(names, message, new_message, &types, &mut changes);
+            }
+        }
+        for (name, service) in &self.services {
+            match new.services.get(name) {
+                Some(new_service) => diff_rpcs(name, service, new_service, &types, &mut changes),
+                None => changes.push(Change::Removed {
+                    kind: ElementKind::Service,
+                    element: name.clone(),
+                    incompatible: true,
+                }),
+            }
+        }
+        for name in new.services.keys() {
+            if !self.services.contains_key(name) {
+                changes.push(Change::Added {
+                    kind: ElementKind::Service,
+                    element: name.clone(),
+                });
+            }
+        }
+        changes
+    }
+
+    /// Report the added, removed and renamed messages, returning the renames. A message is
+    /// considered renamed when one with exactly the same fields was added.
+    fn diff_messages(
+        &self,
+        new: &ProtoContract,
+        changes: &mut Vec<Change>,
+    ) -> BTreeMap<String, String> {
+        let removed: Vec<&String> = self
+            .messages
+            .keys()
+            .filter(|name| !new.messages.contains_key(*name))
+            .collect();
+        let mut added: Vec<&String> = new
+            .messages
+            .keys()
+            .filter(|name| !self.messages.contains_key(*name))
+            .collect();
+
+        let mut renamed = BTreeMap::new();
+        for name in removed {
+            let message = &self.messages[name];
+            match added
+                .iter()
+                .position(|added| new.messages[*added] == *message)
+            {
+                Some(index) => {
+                    let new_name = added.remove(index);
+                    changes.push(Change::Renamed {
+                        kind: ElementKind::Message,
+                        from: name.clone(),
+                        to: new_name.clone(),
+                        incompatible: false,
+                    });
+                    renamed.insert(name.clone(), new_name.clone());
+                }
+                // Where it was used shows up as a type change
+                None => changes.push(Change::Removed {
+                    kind: ElementKind::Message,
+                    element: name.clone(),
+                    incompatible: false,
+                }),
+            }
+        }
+        for name in added {
+            changes.push(Change::Added {
+                kind: ElementKind::Message,
+                element: name.clone(),
+            });
+        }
+        renamed
+    }
+
+    /// Report the enum changes like [`Self::diff_messages`], and the changes of their values.
+    fn diff_enums(
+        &self,
+        new: &ProtoContract,
+        changes: &mut Vec<Change>,
+    ) -> BTreeMap<String, String> {
+        let mut renamed = BTreeMap::new();
+        let mut added: Vec<&String> = new
+            .enums
+            .keys()
+            .filter(|name| !self.enums.contains_key(*name))
+            .collect();
+        for (name, values) in &self.enums {
+            if let Some(new_values) = new.enums.get(name) {
+                diff_enum_values(name, values, new_values, changes);
+                continue;
+            }
+            match added.iter().position(|added| new.enums[*added] == *values) {
+                Some(index) => {
+                    let new_name = added.remove(index);
+                    changes.push(Change::Renamed {
+                        kind: ElementKind::Enum,
+                        from: name.clone(),
+                        to: new_name.clone(),
+                        incompatible: false,
+                    });
+                    renamed.insert(name.clone(), new_name.clone());
+                }
+                None => changes.push(Change::Removed {
+                    kind: ElementKind::Enum,
+                    element: name.clone(),
+                    incompatible: false,
+                }),
+            }
+        }
+        for name in added {
+            changes.push(Change::Added {
+                kind: ElementKind::Enum,
+                element: name.clone(),
+            });
+        }
+        renamed
+    }
+
+    /// The type as it's declared, e.g. `Foo.Status`, resolved like protoc does from the innermost
+    /// scope of the message using it, e.g. `Foo`, outwards. Types of other packages and scalars
+    /// are left as written, without the leading dot.
+    fn resolve(&self, scope: &str, kind: &str) -> String {
+        let declared = |name: &str| {
+            let name = match &self.package {
+                Some(package) => name.strip_prefix(package.as_str())?.strip_prefix('.')?,
+                None => name,
+            };
+            let found = self.messages.contains_key(name) || self.enums.contains_key(name);
+            found.then(|| name.to_string())
+        };
+        if let Some(absolute) = kind.strip_prefix('.') {
+            return declared(absolute).unwrap_or_else(|| absolute.to_string());
+        }
+        let mut scopes: Vec<&str> = self
+            .package
+            .iter()
+            .flat_map(|package| package.split('.'))
+            .chain(scope.split('.'))
+            .filter(|part| !part.is_empty())
+            .collect();
+        loop {
+            let mut candidate = scopes.join(".");
+            if !candidate.is_empty() {
+                candidate.push('.');
+            }
+            candidate.push_str(kind);
+            if let Some(name) = declared(&candidate) {
+                return name;
+            }
+            if scopes.pop().is_none() {
+                return kind.to_string();
+            }
+        }
+    }
+}
+
+/// Resolves the types of the old version to the ones of the new version.
+struct TypeMapping<'a> {
+    old: &'a ProtoContract,
+    new: &'a ProtoContract,
+    renamed: BTreeMap<String, String>,
+}
+
+impl TypeMapping<'_> {
+    /// Resolve the type used in the old version of a message, or in a service with no scope.
+    fn old_type(&self, scope: &str, kind: &str) -> String {
+        self.old.resolve(scope, kind)
+    }
+
+    /// Resolve the type used in the new version of a message, see [`Self::old_type`].
+    fn new_type(&self, scope: &str, kind: &str) -> String {
+        self.new.resolve(scope, kind)
+    }
+
+    /// Whether the resolved types are the same one, possibly renamed.
+    fn is_same(&self, old: &str, new: &str) -> bool {
+        self.renamed.get(old).map_or(old, String::as_str) == new
+    }
+
+    /// Whether a value of the old resolved type is read correctly as the new one.
+    fn is_compatible(&self, old: &str, new: &str) -> bool {
+        if self.is_same(old, new) {
+            return true;
+        }
+        match (
+            self.wire_group(self.old, old),
+            self.wire_group(self.new, new),
+        ) {
+            (Some(old_group), Some(new_group)) => old_group == new_group,
+            _ => false,
+        }
+    }
+
+    /// The scalar types sharing an encoding, which can be changed into each other.
+    fn wire_group(&self, contract: &ProtoContract, kind: &str) -> Option<&'static str> {
+        let group = match kind {
+            "int32" | "int64" | "uint32" | "uint64" | "bool" => "varint",
+            "sint32" | "sint64" => "zigzag",
+            "fixed32" | "sfixed32" => "fixed32",
+            "fixed64" | "sfixed64" => "fixed64",
+            "string" | "bytes" => "length-delimited",
+            kind if contract.enums.contains_key(kind) => "varint",
+            _ => return None,
+        };
+        Some(group)
+    }
+}
+
+/// Report the changes of the fields of a message, with its old and new names.
+fn diff_fields(
+    (old_name, message_name): (&str, &str),
+    old: &Message,
+    new: &Message,
+    types: &TypeMapping,
+    changes: &mut Vec<Change>,
+) {
+    let qualified = |field: &Field| format!("{message_name}.{}", field.name);
+    let mut matched_old = BTreeSet::new();
+    let mut matched_new = BTreeSet::new();
+    let mut pairs = Vec::new();
+    // By name first, then the remaining ones by number, which are renames
+    for (old_index, field) in old.fields.iter().enumerate() {
+        if let Some(new_index) = new.fields.iter().position(|f| f.name == field.name) {
+            pairs.push((old_index, new_index));
+            matched_old.insert(old_index);
+            matched_new.insert(new_index);
+        }
+    }
+    for (old_index, field) in old.fields.iter().enumerate() {
+        if matched_old.contains(&old_index) {
+            continue;
+        }
+        let renamed = new
+            .fields
+            .iter()
+            .enumerate()
+            .find(|(index, f)| f.number == field.number && !matched_new.contains(index));
+        if let Some((new_index, new_field)) = renamed {
+            changes.push(Change::Renamed {
+                kind: ElementKind::Field,
+                from: qualified(field),
+                to: qualified(new_field),
+                incompatible: false,
+            });
+            pairs.push((old_index, new_index));
+            matched_old.insert(old_index);
+            matched_new.insert(new_index);
+        }
+    }
+
+    for (old_index, new_index) in pairs {
+        let (field, new_field) = (&old.fields[old_index], &new.fields[new_index]);
+        let element = qualified(new_field);
+        let mut change = |from: String, to: String, incompatible: bool| {
+            changes.push(Change::Changed {
+                kind: ElementKind::Field,
+                element: element.clone(),
+                from,
+                to,
+                incompatible,
+            })
+        };
+        if field.number != new_field.number {
+            change(
+                format!("number {}", field.number),
+                format!("number {}", new_field.number),
+                true,
+            );
+        }
+        let kind = types.old_type(old_name, &field.kind);
+        let new_kind = types.new_type(message_name, &new_field.kind);
+        if !types.is_same(&kind, &new_kind) {
+            let compatible = types.is_compatible(&kind, &new_kind);
+            change(field.kind.clone(), new_field.kind.clone(), !compatible);
+        }
+        if field.label != new_field.label {
+            let repeated = field.label == Label::Repeated || new_field.label == Label::Repeated;
+            change(
+                field.label.to_string(),
+                new_field.label.to_string(),
+                repeated,
+            );
+        }
+        if field.oneof != new_field.oneof {
+            let oneof = |oneof: &Option<String>| match oneof {
+                Some(oneof) => format!("in oneof {oneof}"),
+                None => "not in a oneof".to_string(),
+            };
+            // Safe for a single field moving into a new oneof, or back out of it, as the other
+            // fields of a oneof are cleared when it's set
+            let alone = |message: &Message, oneof: &Option<String>, other: &Message| {
+                let members = |message: &Message| {
+                    let oneof = oneof.as_ref();
+                    message
+                        .fields
+                        .iter()
+                        .filter(|f| f.oneof.as_ref() == oneof)
+                        .count()
+                };
+                members(message) == 1 && members(other) == 0
+            };
+            let single_move = match (&field.oneof, &new_field.oneof) {
+                (None, Some(_)) => alone(new, &new_field.oneof, old),
+                (Some(_), None) => alone(old, &field.oneof, new),
+                _ => false,
+            };
+            change(oneof(&field.oneof), oneof(&new_field.oneof), !single_move);
+        }
+    }
+
+    for (index, field) in old.fields.iter().enumerate() {
+        if !matched_old.contains(&index) {
+            changes.push(Change::Removed {
+                kind: ElementKind::Field,
+                element: format!("{} = {} ({})", qualified(field), field.number, field.kind),
+                incompatible: false,
+            });
+        }
+    }
+    for (index, field) in new.fields.iter().enumerate() {
+        if !matched_new.contains(&index) {
+            changes.push(Change::Added {
+                kind: ElementKind::Field,
+                element: format!("{} = {} ({})", qualified(field), field.number, field.kind),
+            });
+        }
+    }
+}
+
+fn diff_rpcs(
+    service_name: &str,
+    old: &Service,
+    new: &Service,
+    types: &TypeMapping,
+    changes: &mut Vec<Change>,
+) {
+    let same_signature = |rpc: &Rpc, new_rpc: &Rpc| {
+        rpc.request_stream == new_rpc.request_stream
+            && rpc.response_stream == new_rpc.response_stream
+            && types.is_compatible(
+                &types.old_type("", &rpc.request),
+                &types.new_type("", &new_rpc.request),
+            )
+            && types.is_compatible(
+                &types.old_type("", &rpc.response),
+                &types.new_type("", &new_rpc.response),
+            )
+    };
+    let mut added: Vec<&Rpc> = new
+        .rpcs
+        .iter()
+        .filter(|new_rpc| !old.rpcs.iter().any(|rpc| rpc.name == new_rpc.name))
+        .collect();
+
+    for rpc in &old.rpcs {
+        let qualified = format!("{service_name}.{}", rpc.name);
+        if let Some(new_rpc) = new.rpcs.iter().find(|new_rpc| new_rpc.name == rpc.name) {
+            if !same_signature(rpc, new_rpc) {
+                changes.push(Change::Changed {
+                    kind: ElementKind::Rpc,
+                    element: qualified,
+                    from: rpc.signature(),
+                    to: new_rpc.signature(),
+                    incompatible: true,
+                });
+            }
+            continue;
+        }
+        // The procedures are called by name, so a renamed rpc is as missing as a removed one
+        match added
+            .iter()
+            .position(|new_rpc| same_signature(rpc, new_rpc))
+        {
+            Some(index) => changes.push(Change::Renamed {
+                kind: ElementKind::Rpc,
+                from: qualified,
+                to: format!("{service_name}.{}", added.remove(index).name),
+                incompatible: true,
+            }),
+            None => changes.push(Change::Removed {
+                kind: ElementKind::Rpc,
+                element: format!("{service_name}.{}{}", rpc.name, rpc.signature()),
+                incompatible: true,
+            }),
+        }
+    }
+    for rpc in added {
+        changes.push(Change::Added {
+            kind: ElementKind::Rpc,
+            element: format!("{service_name}.{}{}", rpc.name, rpc.signature()),
+        });
+    }
+}
+
+fn diff_enum_values(name: &str, old: &Enum, new: &Enum, changes: &mut Vec<Change>) {
+    let qualified = |value: &str| format!("{name}.{value}");
+    for (value, number) in &old.values {
+        if let Some((_, new_number)) = new.values.iter().find(|(new_value, _)| new_value == value) {
+            if new_number != number {
+                changes.push(Change::Changed {
+                    kind: ElementKind::EnumValue,
+                    element: qualified(value),
+                    from: number.to_string(),
+                    to: new_number.to_string(),
+                    incompatible: true,
+                });
+            }
+            continue;
+        }
+        let renamed = new.values.iter().find(|(new_value, new_number)| {
+            new_number == number && !old.values.iter().any(|(value, _)| value == new_value)
+        });
+        match renamed {
+            Some((new_value, _)) => changes.push(Change::Renamed {
+                kind: ElementKind::EnumValue,
+                from: qualified(value),
+                to: qualified(new_value),
+                incompatible: false,
+            }),
+            None => changes.push(Change::Removed {
+                kind: ElementKind::EnumValue,
+                element: format!("{} = {number}", qualified(value)),
+                incompatible: false,
+            }),
+        }
+    }
+    for (value, number) in &new.values {
+        let existing = old
+            .values
+            .iter()
+            .any(|(old_value, old_number)| old_value == value || old_number == number);
+        if !existing {
+            changes.push(Change::Added {
+                kind: ElementKind::EnumValue,
+                element: format!("{} = {number}", qualified(value)),
+            });
+        }
+    }
+}
+
+impl Rpc {
+    /// Like `(Payload) returns (stream UsersResponse)`.
+    fn signature(&self) -> String {
+        let stream = |stream: bool| if stream { "stream " } else { "" };
+        format!(
+            "({}{}) returns ({}{})",
+            stream(self.request_stream),
+            self.request,
+            stream(self.response_stream),
+            self.response
+        )
+    }
+}
+
+impl fmt::Display for Label {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self {
+            Label::Singular => "singular",
+            Label::Optional => "optional",
+            Label::Repeated => "repeated",
+        };
+        f.write_str(label)
+    }
+}
+
+struct Token {
+    text: String,
+    line: usize,
+}
+
+impl Token {
+    fn error(&self, message: String) -> ProtoParseError {
+        ProtoParseError {
+            line: self.line,
+            message,
+        }
+    }
+}
+
+/// Split into identifiers (dotted names included), numbers, strings and symbols, without comments.
+fn tokenize(content: &str) -> Result<Vec<Token>, ProtoParseError> {
+    let mut tokens = Vec::new();
+    let mut chars = content.chars().peekable();
+    let mut line = 1;
+    while let Some(char) = chars.next() {
+        match char {
+            '\n' => line += 1,
+            char if char.is_whitespace() => {}
+            '/' if chars.peek() == Some(&'/') => {
+                while chars.next_if(|char| *char != '\n').is_some() {}
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                loop {
+                    match chars.next() {
+                        Some('/') if previous == '*' => break,
+                        Some(char) => {
+                            if char == '\n' {
+                                line += 1;
+                            }
+                            previous = char;
+                        }
+                        None => {
+                            return Err(ProtoParseError {
+                                line,
+                                message: "unterminated comment".to_string(),
+                            })
+                        }
+                    }
+                }
+            }
+            '"' | '\'' => {
+                let mut text = String::from(char);
+                loop {
+                    match chars.next() {
+                        Some('\\') => {
+                            text.push('\\');
+                            text.extend(chars.next());
+                        }
+                        Some(quote) if quote == char => break,
+                        Some(other) => text.push(other),
+                        None => {
+                            return Err(ProtoParseError {
+                                line,
+                                message: "unterminated string".to_string(),
+                            })
+                        }
+                    }
+                }
+                text.push(char);
+                tokens.push(Token { text, line });
+            }
+            char if char.is_alphanumeric() || matches!(char, '_' | '.' | '-' | '+') => {
+                let mut text = String::from(char);
+                while let Some(char) =
+                    chars.next_if(|char| char.is_alphanumeric() || matches!(char, '_' | '.'))
+                {
+                    text.push(char);
+                }
+                tokens.push(Token { text, line });
+            }
+            symbol => tokens.push(Token {
+                text: symbol.to_string(),
+                line,
+            }),
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.position)?;
+        self.position += 1;
+        Some(token)
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens
+            .get(self.position)
+            .map(|token| token.text.as_str())
+    }
+
+    fn error_at_end(&self, message: String) -> ProtoParseError {
+        ProtoParseError {
+            line: self.tokens.last().map_or(1, |token| token.line),
+            message,
+        }
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), ProtoParseError> {
+        match self.next() {
+            Some(token) if token.text == expected => Ok(()),
+            Some(token) => {
+                Err(token.error(format!("expected `{expected}`, found `{}`", token.text)))
+            }
+            None => Err(self.error_at_end(format!("expected `{expected}`"))),
+        }
+    }
+
+    fn expect_ident(&mut self) -> Result<String, ProtoParseError> {
+        match self.next() {
+            Some(token)
+                if token.text.starts_with(|char: char| {
+                    char.is_alphabetic() || char == '_' || char == '.'
+                }) =>
+            {
+                Ok(token.text.clone())
+            }
+            Some(token) => Err(token.error(format!("expected a name, found `{}`", token.text))),
+            None => Err(self.error_at_end("expected a name".to_string())),
+        }
+    }
+
+    fn expect_number<T: std::str::FromStr>(&mut self) -> Result<T, ProtoParseError> {
+        match self.next() {
+            Some(token) => {
+                let text = token.text.clone();
+                let parsed = match text.strip_prefix("0x") {
+                    Some(hex) => i64::from_str_radix(hex, 16).ok().map(|n| n.to_string()),
+                    None => Some(text.clone()),
+                };
+                parsed
+                    .and_then(|number| number.parse().ok())
+                    .ok_or_else(|| token.error(format!("expected a number, found `{text}`")))
+            }
+            None => Err(self.error_at_end("expected a number".to_string())),
+        }
+    }
+
+    /// Skip everything up to the end of the statement, e.g. an option.
+    fn skip_statement(&mut self) -> Result<(), ProtoParseError> {
+        while let Some(token) = self.next() {
+            match token.text.as_str() {
+                ";" => return Ok(()),
+                "{" => {
+                    self.position -= 1;
+                    return self.skip_block();
+                }
+                _ => {}
+            }
+        }
+        Err(self.error_at_end("expected `;`".to_string()))
+    }
+
+    /// Skip everything up to the end of the next block, nested ones included.
+    fn skip_block(&mut self) -> Result<(), ProtoParseError> {
+        let mut depth = 0;
+        while let Some(token) = self.next() {
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" if depth == 1 => return Ok(()),
+                "}" => depth -= 1,
+                _ => {}
+            }
+        }
+        Err(self.error_at_end("expected `}`".to_string()))
+    }
+
+    fn message(
+        &mut self,
+        parent: &str,
+        contract: &mut ProtoContract,
+    ) -> Result<(), ProtoParseError> {
+        let name = qualify(parent, &self.expect_ident()?);
+        self.expect("{")?;
+        let mut message = Message::default();
+        loop {
+            match self.peek() {
+                Some("}") => {
+                    self.position += 1;
+                    break;
+                }
+                Some("message") => {
+                    self.position += 1;
+                    self.message(&name, contract)?;
+                }
+                Some("enum") => {
+                    self.position += 1;
+                    self.enumeration(&name, contract)?;
+                }
+                Some("oneof") => {
+                    self.position += 1;
+                    let oneof = self.expect_ident()?;
+                    self.expect("{")?;
+                    while self.peek() != Some("}") {
+                        if self.peek() == Some("option") {
+                            self.skip_statement()?;
+                        } else {
+                            message.fields.push(self.field(Some(&oneof))?);
+                        }
+                    }
+                    self.position += 1;
+                }
+                Some("option" | "reserved" | "extensions" | "extend") => self.skip_statement()?,
+                Some(";") => self.position += 1,
+                Some(_) => message.fields.push(self.field(None)?),
+                None => return Err(self.error_at_end(format!("unterminated message {name}"))),
+            }
+        }
+        contract.messages.insert(name, message);
+        Ok(())
+    }
+
+    fn field(&mut self, oneof: Option<&str>) -> Result<Field, ProtoParseError> {
+        let label = match self.peek() {
+            Some("optional") => Label::Optional,
+            Some("repeated") => Label::Repeated,
+            _ => Label::Singular,
+        };
+        if label != Label::Singular {
+            self.position += 1;
+        }
+        let mut kind = self.expect_ident()?;
+        if kind == "map" {
+            self.expect("<")?;
+            let key = self.expect_ident()?;
+            self.expect(",")?;
+            let value = self.expect_ident()?;
+            self.expect(">")?;
+            kind = format!("map<{key}, {value}>");
+        }
+        let name = self.expect_ident()?;
+        self.expect("=")?;
+        let number = self.expect_number()?;
+        if self.peek() == Some("[") {
+            while self.next().is_some_and(|token| token.text != "]") {}
+        }
+        self.expect(";")?;
+        Ok(Field {
+            name,
+            number,
+            label,
+            kind,
+            oneof: oneof.map(str::to_string),
+        })
+    }
+
+    fn enumeration(
+        &mut self,
+        parent: &str,
+        contract: &mut ProtoContract,
+    ) -> Result<(), ProtoParseError> {
+        let name = qualify(parent, &self.expect_ident()?);
+        self.expect("{")?;
+        let mut values = Enum::default();
+        loop {
+            match self.peek() {
+                Some("}") => {
+                    self.position += 1;
+                    break;
+                }
+                Some("option" | "reserved") => self.skip_statement()?,
+                Some(";") => self.position += 1,
+                Some(_) => {
+                    let value = self.expect_ident()?;
+                    self.expect("=")?;
+                    let number = self.expect_number()?;
+                    self.skip_statement()?;
+                    values.values.push((value, number));
+                }
+                None => return Err(self.error_at_end(format!("unterminated enum {name}"))),
+            }
+        }
+        contract.enums.insert(name, values);
+        Ok(())
+    }
+
+    fn service(&mut self, contract: &mut ProtoContract) -> Result<(), ProtoParseError> {
+        let name = self.expect_ident()?;
+        self.expect("{")?;
+        let mut service = Service::default();
+        loop {
+            match self.peek() {
+                Some("}") => {
+                    self.position += 1;
+                    break;
+                }
+                Some("rpc") => {
+                    self.position += 1;
+                    service.rpcs.push(self.rpc()?);
+                }
+                Some(";") => self.position += 1,
+                Some(_) => self.skip_statement()?,
+                None => return Err(self.error_at_end(format!("unterminated service {name}"))),
+            }
+        }
+        contract.services.insert(name, service);
+        Ok(())
+    }
+
+    fn rpc(&mut self) -> Result<Rpc, ProtoParseError> {
+        let name = self.expect_ident()?;
+        let (request_stream, request) = self.rpc_type()?;
+        self.expect("returns")?;
+        let (response_stream, response) = self.rpc_type()?;
+        match self.peek() {
+            Some("{") => self.skip_block()?,
+            _ => self.expect(";")?,
+        }
+        Ok(Rpc {
+            name,
+            request,
+            request_stream,
+            response,
+            response_stream,
+        })
+    }
+
+    /// Like `(stream Payload)`.
+    fn rpc_type(&mut self) -> Result<(bool, String), ProtoParseError> {
+        self.expect("(")?;
+        let mut kind = self.expect_ident()?;
+        let stream = kind == "stream" && self.peek() != Some(")");
+        if stream {
+            kind = self.expect_ident()?;
+        }
+        self.expect(")")?;
+        Ok((stream, kind))
+    }
+}
+
+fn qualify(parent: &str, name: &str) -> String {
+    if parent.is_empty() {
+        name.to_string()
+    } else {
+        format!("{parent}.{name}")
+    }
+}
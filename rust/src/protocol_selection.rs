@@ -0,0 +1,24 @@
+//! Selection of the friendships protocol version a build uses, shared with `build.rs`.
+//!
+//! A build uses a single version: the client, the flows, the mock server and the binaries use the
+//! types of the selected one directly, re-exported as `social_client::protocol`.
+
+/// The version the build uses among the `enabled` ones, listed oldest first: the `requested` one,
+/// from `SOCIAL_CLIENT_PROTOCOL`, or else the newest one, `None` if none is enabled.
+///
+/// Fails if the requested version isn't enabled.
+pub fn select_protocol<'a>(
+    enabled: &[&'a str],
+    requested: Option<&str>,
+) -> Result<Option<&'a str>, String> {
+    match requested {
+        Some(requested) => match enabled.iter().find(|version| **version == requested) {
+            Some(version) => Ok(Some(version)),
+            None => Err(format!(
+                "{requested:?} isn't one of the enabled versions: {}",
+                enabled.join(", ")
+            )),
+        },
+        None => Ok(enabled.last().copied()),
+    }
+}
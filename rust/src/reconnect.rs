@@ -0,0 +1,307 @@
+use std::{future::Future, sync::Arc, time::Duration};
+
+use rand::Rng;
+use tokio::sync::Mutex;
+
+use crate::{
+    client::SocialClient,
+    credentials::AuthUser,
+    error::SocialClientError,
+    friendship_state::{poll_friendship_state, FriendshipState},
+    ServerStreamResponse, SubscribeFriendshipEventsUpdatesResponse,
+};
+
+/// Exponential backoff with jitter applied between connection attempts, and between the attempts
+/// of a call failing because of the connection.
+#[derive(Clone, Debug)]
+pub struct Backoff {
+    /// Delay before the second attempt, the first one is immediate.
+    pub initial_delay: Duration,
+    /// Upper bound for the delay, before applying the jitter.
+    pub max_delay: Duration,
+    /// Factor applied to the delay after each failed attempt, the delay is `max_delay` when it
+    /// isn't a positive number.
+    pub multiplier: f64,
+    /// Fraction of the delay randomly added or subtracted, between `0.0` and `1.0`.
+    pub jitter: f64,
+    /// Give up after this many attempts to connect, or to call a procedure on a new connection,
+    /// `None` retries forever.
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.2,
+            max_attempts: None,
+        }
+    }
+}
+
+impl Backoff {
+    /// The delay to wait after the given failed attempt, starting at 1.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let delay = (self.initial_delay.as_secs_f64() * self.multiplier.powi(exponent))
+            .min(self.max_delay.as_secs_f64());
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        let factor = if jitter > 0.0 {
+            rand::thread_rng().gen_range(1.0 - jitter..=1.0 + jitter)
+        } else {
+            1.0
+        };
+        // A negative or NaN multiplier makes a negative or NaN delay
+        Duration::try_from_secs_f64(delay * factor).unwrap_or(self.max_delay)
+    }
+
+    fn gives_up_after(&self, attempt: u32) -> bool {
+        matches!(self.max_attempts, Some(max_attempts) if attempt >= max_attempts)
+    }
+}
+
+/// State of a [`ReconnectingClient`], reported to its state change hook.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connecting { attempt: u32 },
+    Connected,
+    Disconnected,
+    GaveUp { attempts: u32 },
+}
+
+type StateChangeHook = Arc<dyn Fn(&ConnectionState) + Send + Sync>;
+
+/// A [`SocialClient`] that transparently reconnects when the connection is lost.
+///
+/// dcl-rpc doesn't fail pending calls when the WebSocket is closed, so every call is bounded by
+/// a request timeout and a timed out call is treated as a lost connection.
+///
+/// When the social service rejects the token of a user with a
+/// [`TokenRefresher`](crate::credentials::TokenRefresher), the token is refreshed and the call
+/// retried on a new connection authenticated with it.
+pub struct ReconnectingClient {
+    hosts: Vec<String>,
+    address: String,
+    user: std::sync::Mutex<AuthUser>,
+    backoff: Backoff,
+    request_timeout: Duration,
+    on_state_change: Option<StateChangeHook>,
+    current: Mutex<Option<Arc<SocialClient>>>,
+}
+
+impl ReconnectingClient {
+    pub fn new(host: &str, user: AuthUser) -> Self {
+        Self::for_hosts(&[host.to_string()], user)
+    }
+
+    /// A client connecting to the first of the given hosts, each attempt to connect going to the
+    /// next one, wrapping around.
+    ///
+    /// # Panics
+    ///
+    /// If there are no hosts.
+    pub fn for_hosts(hosts: &[String], user: AuthUser) -> Self {
+        assert!(!hosts.is_empty(), "a client needs a host to connect to");
+        Self {
+            hosts: hosts.to_vec(),
+            address: user.address.clone(),
+            user: std::sync::Mutex::new(user),
+            backoff: Backoff::default(),
+            request_timeout: Duration::from_secs(20),
+            on_state_change: None,
+            current: Mutex::new(None),
+        }
+    }
+
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    pub fn with_request_timeout(mut self, request_timeout: Duration) -> Self {
+        self.request_timeout = request_timeout;
+        self
+    }
+
+    /// Register a hook called on every connection state change.
+    pub fn on_state_change<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&ConnectionState) + Send + Sync + 'static,
+    {
+        self.on_state_change = Some(Arc::new(hook));
+        self
+    }
+
+    /// The user with its current token.
+    pub fn user(&self) -> AuthUser {
+        self.user.lock().unwrap().clone()
+    }
+
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    pub fn request_timeout(&self) -> Duration {
+        self.request_timeout
+    }
+
+    /// Get the current connection, connecting with backoff if there is none.
+    pub async fn client(&self) -> Result<Arc<SocialClient>, SocialClientError> {
+        let mut current = self.current.lock().await;
+        if let Some(client) = current.as_ref() {
+            return Ok(client.clone());
+        }
+
+        let mut attempt = 1;
+        loop {
+            self.notify(ConnectionState::Connecting { attempt });
+            let host = &self.hosts[(attempt - 1) as usize % self.hosts.len()];
+            match SocialClient::connect(host, self.user()).await {
+                Ok(client) => {
+                    let client = Arc::new(client);
+                    *current = Some(client.clone());
+                    self.notify(ConnectionState::Connected);
+                    return Ok(client);
+                }
+                Err(err) => {
+                    if self.backoff.gives_up_after(attempt) {
+                        self.notify(ConnectionState::GaveUp { attempts: attempt });
+                        return Err(SocialClientError::ReconnectGaveUp {
+                            attempts: attempt,
+                            last_error: Box::new(err),
+                        });
+                    }
+                    tokio::time::sleep(self.backoff.delay(attempt)).await;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    /// Drop the given connection so the next call reconnects.
+    ///
+    /// Does nothing if the connection was already replaced by another caller.
+    pub async fn disconnect(&self, client: &Arc<SocialClient>) {
+        let mut current = self.current.lock().await;
+        if matches!(current.as_ref(), Some(current) if Arc::ptr_eq(current, client)) {
+            *current = None;
+            self.notify(ConnectionState::Disconnected);
+        }
+    }
+
+    /// Run a procedure on the current connection, reconnecting and retrying it after the delay of
+    /// the backoff while it fails because of the connection, up to the max attempts of the backoff.
+    ///
+    /// A procedure rejected as unauthorized is retried once with a refreshed token if the user
+    /// has a token refresher.
+    ///
+    /// Note that a friendship event update may be applied twice if the connection is lost after
+    /// the server received it.
+    pub async fn call<T, F, Fut>(&self, procedure: F) -> Result<T, SocialClientError>
+    where
+        F: Fn(Arc<SocialClient>) -> Fut,
+        Fut: Future<Output = Result<T, SocialClientError>>,
+    {
+        let mut refreshed = false;
+        let mut attempt = 1;
+        loop {
+            let client = self.client().await?;
+            let result = tokio::time::timeout(self.request_timeout, procedure(client.clone()))
+                .await
+                .unwrap_or(Err(SocialClientError::Timeout(self.request_timeout)));
+            match result {
+                Err(err) if err.is_connection_error() => {
+                    self.disconnect(&client).await;
+                    if self.backoff.gives_up_after(attempt) {
+                        self.notify(ConnectionState::GaveUp { attempts: attempt });
+                        return Err(SocialClientError::ReconnectGaveUp {
+                            attempts: attempt,
+                            last_error: Box::new(err),
+                        });
+                    }
+                    log::warn!("Connection lost: {err}, reconnecting...");
+                    tokio::time::sleep(self.backoff.delay(attempt)).await;
+                    attempt += 1;
+                }
+                Err(SocialClientError::Unauthorized(message))
+                    if !refreshed && self.refresh_token(&client).await =>
+                {
+                    log::warn!("Token rejected: {message}, retrying with a refreshed one...");
+                    refreshed = true;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Replace the token of the user with a refreshed one and drop the connection authenticated
+    /// with the old one, returning whether the token could be refreshed.
+    async fn refresh_token(&self, client: &Arc<SocialClient>) -> bool {
+        let user = self.user();
+        let Some(refresher) = user.token_refresher() else {
+            return false;
+        };
+        match refresher.refresh_token(&user).await {
+            Ok(token) => {
+                self.user.lock().unwrap().token = token;
+                self.disconnect(client).await;
+                true
+            }
+            Err(err) => {
+                log::error!("Failed to refresh the token of {}: {err}", self.address);
+                false
+            }
+        }
+    }
+
+    /// Subscribe to the friendship events updates of the user, reconnecting while it fails
+    /// because of the connection.
+    pub async fn subscribe_friendship_events_updates(
+        &self,
+    ) -> Result<Subscription, SocialClientError> {
+        self.call(|client| async move {
+            let updates = client.subscribe_friendship_events_updates().await?;
+            Ok(Subscription { client, updates })
+        })
+        .await
+    }
+
+    /// Wait until the friendship between the user and the given address is the expected one,
+    /// failing if it isn't within the given timeout.
+    pub async fn wait_for_friendship_state(
+        &self,
+        address: &str,
+        expected: FriendshipState,
+        timeout: Duration,
+    ) -> Result<(), SocialClientError> {
+        poll_friendship_state(address, expected, timeout, || {
+            self.call(|client| async move { client.friendship_state(address).await })
+        })
+        .await
+    }
+
+    fn notify(&self, state: ConnectionState) {
+        if let Some(hook) = &self.on_state_change {
+            hook(&state);
+        }
+    }
+}
+
+/// A friendship events updates stream along with the connection it was opened on.
+pub struct Subscription {
+    client: Arc<SocialClient>,
+    updates: ServerStreamResponse<SubscribeFriendshipEventsUpdatesResponse>,
+}
+
+impl Subscription {
+    /// The connection the stream was opened on, to be passed to [`ReconnectingClient::disconnect`].
+    pub fn client(&self) -> &Arc<SocialClient> {
+        &self.client
+    }
+
+    pub async fn next(&mut self) -> Option<SubscribeFriendshipEventsUpdatesResponse> {
+        self.updates.next().await
+    }
+}
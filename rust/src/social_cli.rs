@@ -0,0 +1,66 @@
+use social_client::cli::{CliArgs, OutputFormat};
+use social_client::config::load_environment;
+use social_client::credentials::{load_users, CredentialsOptions};
+use social_client::token::check_tokens;
+use std::{env, io, process::ExitCode};
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let environment = match load_environment(&mut args) {
+        Ok(environment) => environment,
+        Err(err) => {
+            println!("Invalid configuration: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let options = match CredentialsOptions::from_args(&mut args) {
+        Ok(options) => options,
+        Err(err) => {
+            println!("Failed to load credentials: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let args = match CliArgs::parse(&args) {
+        Ok(args) => args,
+        Err(err) => {
+            println!("{err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let users = match load_users(&options, 1).await {
+        Ok(users) => users,
+        Err(err) => {
+            println!("Failed to load credentials: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let acting_users = match args.users(&users) {
+        Ok(acting_users) => acting_users,
+        Err(err) => {
+            println!("{err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    match check_tokens(acting_users) {
+        Ok(warnings) => {
+            for warning in warnings {
+                eprintln!("Warning: {warning}");
+            }
+        }
+        Err(err) => {
+            println!("Refusing to start: {err}");
+            return ExitCode::FAILURE;
+        }
+    }
+
+    if let Err(err) = args.run(&users, &environment, &mut io::stdout()).await {
+        match args.output {
+            // Keep the output parseable
+            OutputFormat::Json => eprintln!("{err}"),
+            OutputFormat::Text => println!("{err}"),
+        }
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
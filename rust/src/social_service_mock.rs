@@ -0,0 +1,46 @@
+use std::{env, process::ExitCode};
+
+use social_client::config::load_environment;
+use social_client::credentials::load_users_from_args;
+use social_client::mock_server::MockSocialServer;
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    // Hosts
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let environment = match load_environment(&mut args) {
+        Ok(environment) => environment,
+        Err(err) => {
+            println!("Invalid configuration: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    // Auth Users
+    let users = match load_users_from_args(&mut args, 3).await {
+        Ok(users) => users,
+        Err(err) => {
+            println!("Failed to load credentials: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut server = MockSocialServer::start(users.users());
+    for host in environment.all_hosts() {
+        // The mock only serves plain WebSockets
+        let Some(address) = host.strip_prefix("ws://") else {
+            println!("Can't listen on {host}, only ws:// hosts are supported");
+            return ExitCode::FAILURE;
+        };
+        if let Err(err) = server.add_listener(address).await {
+            println!("Failed to listen on {address}: {err}");
+            return ExitCode::FAILURE;
+        }
+    }
+
+    println!(
+        "Mock social service listening on {}...",
+        server.hosts().join(", ")
+    );
+    std::future::pending().await
+}
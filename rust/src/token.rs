@@ -0,0 +1,224 @@
+use std::{
+    fmt,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use base64::{
+    engine::general_purpose::{STANDARD_NO_PAD, URL_SAFE_NO_PAD},
+    Engine,
+};
+
+use crate::{credentials::AuthUser, error::SocialClientError};
+
+/// Tokens expiring sooner than this are reported as about to expire.
+pub const EXPIRY_WARNING: Duration = Duration::from_secs(10 * 60);
+
+/// The format of a token, which tells what can be known about it without asking the server.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenKind {
+    /// A JSON Web Token, whose claims are readable.
+    Jwt,
+    /// A Decentraland auth chain, whose signer and ephemeral key expiration are readable.
+    AuthChain,
+    /// A Synapse access token (`syt_<localpart>_...`), whose user is readable but not its expiry.
+    Synapse,
+    /// Anything else, nothing is known about it.
+    Opaque,
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            TokenKind::Jwt => "JWT",
+            TokenKind::AuthChain => "auth chain",
+            TokenKind::Synapse => "Synapse access token",
+            TokenKind::Opaque => "opaque token",
+        };
+        f.write_str(kind)
+    }
+}
+
+/// What was decoded from a token locally.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TokenInfo {
+    pub kind: TokenKind,
+    /// The address the token was issued to, lowercase.
+    pub subject: Option<String>,
+    pub issued_at: Option<SystemTime>,
+    pub expires_at: Option<SystemTime>,
+}
+
+impl TokenInfo {
+    pub fn is_expired(&self) -> bool {
+        matches!(self.expires_at, Some(expires_at) if expires_at <= SystemTime::now())
+    }
+
+    /// Whether the token expires within the given time, or already expired.
+    pub fn expires_within(&self, duration: Duration) -> bool {
+        matches!(self.expires_at, Some(expires_at) if expires_at <= SystemTime::now() + duration)
+    }
+
+    /// Whether the token was issued to the given address, `None` if its subject is unknown.
+    pub fn matches_address(&self, address: &str) -> Option<bool> {
+        let subject = self.subject.as_ref()?;
+        Some(subject.eq_ignore_ascii_case(address))
+    }
+
+    /// Why the token may not work for the given address: it was issued to another one, or it
+    /// expires within [`EXPIRY_WARNING`].
+    pub fn warnings(&self, address: &str) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if let (Some(false), Some(subject)) = (self.matches_address(address), &self.subject) {
+            warnings.push(format!("the token of {address} was issued to {subject}"));
+        }
+        match self.expires_at {
+            Some(expires_at) if !self.is_expired() && self.expires_within(EXPIRY_WARNING) => {
+                warnings.push(format!(
+                    "the token of {address} expires at {}",
+                    humantime::format_rfc3339_seconds(expires_at)
+                ))
+            }
+            _ => {}
+        }
+        warnings
+    }
+}
+
+impl fmt::Display for TokenInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(subject) = &self.subject {
+            write!(f, " for {subject}")?;
+        }
+        if let Some(issued_at) = self.issued_at {
+            write!(
+                f,
+                ", issued at {}",
+                humantime::format_rfc3339_seconds(issued_at)
+            )?;
+        }
+        match self.expires_at {
+            Some(expires_at) if self.is_expired() => write!(
+                f,
+                ", expired at {}",
+                humantime::format_rfc3339_seconds(expires_at)
+            ),
+            Some(expires_at) => write!(
+                f,
+                ", expires at {}",
+                humantime::format_rfc3339_seconds(expires_at)
+            ),
+            None => write!(f, ", expiry unknown"),
+        }
+    }
+}
+
+/// Decode what can be decoded from the given token without asking the server.
+pub fn inspect_token(token: &str) -> TokenInfo {
+    let token = token.trim();
+    inspect_jwt(token)
+        .or_else(|| inspect_auth_chain(token))
+        .or_else(|| inspect_synapse_token(token))
+        .unwrap_or(TokenInfo {
+            kind: TokenKind::Opaque,
+            subject: None,
+            issued_at: None,
+            expires_at: None,
+        })
+}
+
+/// Inspect the token of the given user, logging its [warnings](TokenInfo::warnings) and failing
+/// with [`SocialClientError::TokenExpired`] if it already expired.
+pub fn check_token(user: &AuthUser) -> Result<TokenInfo, SocialClientError> {
+    let info = inspect_unexpired(user)?;
+    for warning in info.warnings(&user.address) {
+        log::warn!("{warning}");
+    }
+    Ok(info)
+}
+
+/// Check the tokens of the given users before starting, returning their warnings or failing with
+/// [`SocialClientError::TokenExpired`] if any of them already expired.
+pub fn check_tokens<'a>(
+    users: impl IntoIterator<Item = &'a AuthUser>,
+) -> Result<Vec<String>, SocialClientError> {
+    let mut warnings = Vec::new();
+    for user in users {
+        warnings.extend(inspect_unexpired(user)?.warnings(&user.address));
+    }
+    Ok(warnings)
+}
+
+fn inspect_unexpired(user: &AuthUser) -> Result<TokenInfo, SocialClientError> {
+    let info = inspect_token(user.token.expose());
+    match info.expires_at {
+        Some(expired_at) if info.is_expired() => Err(SocialClientError::TokenExpired {
+            address: user.address.clone(),
+            expired_at,
+        }),
+        _ => Ok(info),
+    }
+}
+
+fn inspect_jwt(token: &str) -> Option<TokenInfo> {
+    let [_header, claims, _signature] = token.split('.').collect::<Vec<_>>()[..] else {
+        return None;
+    };
+    let claims: serde_json::Value =
+        serde_json::from_slice(&URL_SAFE_NO_PAD.decode(claims).ok()?).ok()?;
+    let time = |claim: &str| {
+        claims[claim]
+            .as_u64()
+            .map(|seconds| UNIX_EPOCH + Duration::from_secs(seconds))
+    };
+    Some(TokenInfo {
+        kind: TokenKind::Jwt,
+        subject: claims["sub"].as_str().map(address_of_user_id),
+        issued_at: time("iat"),
+        expires_at: time("exp"),
+    })
+}
+
+fn inspect_auth_chain(token: &str) -> Option<TokenInfo> {
+    let chain: Vec<serde_json::Value> = serde_json::from_str(token).ok()?;
+    let payload = |kind: &str| {
+        chain
+            .iter()
+            .find(|link| link["type"] == kind)
+            .and_then(|link| link["payload"].as_str())
+    };
+    let subject = payload("SIGNER")?;
+    let expires_at = payload("ECDSA_EPHEMERAL").and_then(|message| {
+        let expiration = message
+            .lines()
+            .find_map(|line| line.strip_prefix("Expiration: "))?;
+        humantime::parse_rfc3339_weak(expiration.trim()).ok()
+    });
+    // Logins sign their timestamp in milliseconds
+    let issued_at = payload("ECDSA_SIGNED_ENTITY")
+        .and_then(|payload| payload.parse::<u64>().ok())
+        .map(|millis| UNIX_EPOCH + Duration::from_millis(millis));
+    Some(TokenInfo {
+        kind: TokenKind::AuthChain,
+        subject: Some(subject.to_lowercase()),
+        issued_at,
+        expires_at,
+    })
+}
+
+fn inspect_synapse_token(token: &str) -> Option<TokenInfo> {
+    let localpart = token.strip_prefix("syt_")?.split('_').next()?;
+    let localpart = String::from_utf8(STANDARD_NO_PAD.decode(localpart).ok()?).ok()?;
+    Some(TokenInfo {
+        kind: TokenKind::Synapse,
+        subject: Some(localpart.to_lowercase()),
+        issued_at: None,
+        expires_at: None,
+    })
+}
+
+/// The address of a Matrix user id like `@0xabc...:decentraland.zone`, or of a bare address.
+fn address_of_user_id(user_id: &str) -> String {
+    let user_id = user_id.strip_prefix('@').unwrap_or(user_id);
+    user_id.split(':').next().unwrap_or(user_id).to_lowercase()
+}
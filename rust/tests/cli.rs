@@ -0,0 +1,203 @@
+use std::collections::BTreeMap;
+
+use social_client::{
+    cli::{CliArgs, CliError, Command, OutputFormat},
+    config::Environment,
+    credentials::{AuthUser, Users},
+    friendship_procedures::Flow,
+    friendship_state::FriendshipAction,
+    mock_server::MockSocialServer,
+    Payload, UpdateFriendshipPayload,
+};
+
+const ADDRESS_A: &str = "0x00000000000000000000000000000000000000aa";
+const ADDRESS_B: &str = "0x00000000000000000000000000000000000000bb";
+
+fn parse(args: &str) -> Result<CliArgs, CliError> {
+    let args: Vec<String> = args.split_whitespace().map(str::to_string).collect();
+    CliArgs::parse(&args)
+}
+
+#[test]
+fn commands_are_parsed() {
+    assert_eq!(
+        parse("--as bob request alice --message hi").unwrap(),
+        CliArgs {
+            user: Some("bob".to_string()),
+            command: Command::Update {
+                action: FriendshipAction::Request,
+                target: "alice".to_string(),
+                message: Some("hi".to_string()),
+            },
+            output: OutputFormat::Text,
+        }
+    );
+    assert_eq!(
+        parse("delete 0xbb").unwrap().command,
+        Command::Update {
+            action: FriendshipAction::Delete,
+            target: "0xbb".to_string(),
+            message: None,
+        }
+    );
+    assert_eq!(
+        parse("run-flow flow2 B").unwrap().command,
+        Command::RunFlow {
+            flow: Flow::Flow2,
+            target: "B".to_string(),
+        }
+    );
+    assert_eq!(parse("friends").unwrap().user, None);
+    assert_eq!(
+        parse("--output json friends").unwrap().output,
+        OutputFormat::Json
+    );
+
+    for invalid in [
+        "",
+        "friends B",
+        "accept",
+        "accept B --message hi",
+        "run-flow request B",
+        "listen --verbose",
+        "--output yaml friends",
+    ] {
+        assert!(
+            matches!(parse(invalid), Err(CliError::Usage(_))),
+            "{invalid:?} was accepted"
+        );
+    }
+}
+
+#[tokio::test]
+async fn commands_run_against_the_social_service() {
+    let user_a = AuthUser::new(ADDRESS_A, "token-a");
+    let user_b = AuthUser::new(ADDRESS_B, "token-b");
+    let server = MockSocialServer::listen("127.0.0.1:0", &[user_a.clone(), user_b.clone()])
+        .await
+        .unwrap();
+    let environment = Environment {
+        name: "mock".to_string(),
+        hosts: vec![server.host().unwrap().to_string()],
+        ..Default::default()
+    };
+    let mut users = Users::default();
+    users.insert("alice", user_a);
+    users.insert("bob", user_b);
+
+    let run = |args: &str| {
+        let (args, users, environment) = (args.to_string(), users.clone(), environment.clone());
+        async move {
+            let mut out = Vec::new();
+            parse(&args)
+                .unwrap()
+                .run(&users, &environment, &mut out)
+                .await
+                .map(|()| String::from_utf8(out).unwrap())
+        }
+    };
+
+    assert_eq!(
+        run("request bob --message hi").await.unwrap(),
+        format!("request {ADDRESS_B}: hi\n")
+    );
+    let requests = run("--as bob requests").await.unwrap();
+    assert!(requests.starts_with(&format!("Incoming requests: 1\n  {ADDRESS_A} at ")));
+    assert!(requests.ends_with(": hi\nOutgoing requests: 0\n"));
+    let requests: serde_json::Value =
+        serde_json::from_str(&run("--as bob --output json requests").await.unwrap()).unwrap();
+    let request = &requests["incoming"]["items"][0];
+    assert_eq!(request["user"]["address"], ADDRESS_A);
+    assert_eq!(request["message"], "hi");
+    assert!(request["created_at"].is_i64());
+
+    run(&format!("--as {ADDRESS_B} accept alice"))
+        .await
+        .unwrap();
+    assert_eq!(run("friends").await.unwrap(), format!("{ADDRESS_B}\n"));
+    assert_eq!(
+        run("--output json friends").await.unwrap(),
+        format!("{{\"address\":\"{ADDRESS_B}\"}}\n")
+    );
+
+    assert!(matches!(
+        run("--as carol friends").await,
+        Err(CliError::UnknownUser { user, .. }) if user == "carol"
+    ));
+    assert!(matches!(run("accept bob").await, Err(CliError::Client(_))));
+}
+
+#[test]
+fn only_the_users_of_the_command_are_acting() {
+    let mut users = Users::default();
+    users.insert("alice", AuthUser::new(ADDRESS_A, "token-a"));
+    users.insert("bob", AuthUser::new(ADDRESS_B, "token-b"));
+    users.insert("carol", AuthUser::new("0xcc", "token-c"));
+    let acting = |args: &str| {
+        parse(args).unwrap().users(&users).map(|acting| {
+            acting
+                .iter()
+                .map(|user| user.address.clone())
+                .collect::<Vec<_>>()
+        })
+    };
+
+    assert_eq!(acting("friends").unwrap(), [ADDRESS_A]);
+    assert_eq!(acting("--as bob request carol").unwrap(), [ADDRESS_B]);
+    assert_eq!(
+        acting("--as carol run-flow flow1 bob").unwrap(),
+        ["0xcc", ADDRESS_B]
+    );
+    assert!(matches!(
+        acting("run-flow flow1 0xdd"),
+        Err(CliError::UnknownUser { user, .. }) if user == "0xdd"
+    ));
+    assert!(matches!(
+        parse("friends").unwrap().users(&Users::default()),
+        Err(CliError::Usage(_))
+    ));
+}
+
+#[test]
+fn tokens_are_not_serialized() {
+    let payload = UpdateFriendshipPayload {
+        event: None,
+        auth_token: Some(Payload {
+            synapse_token: Some("token-a".to_string()),
+        }),
+    };
+    let json = serde_json::to_string(&payload).unwrap();
+    assert_eq!(json, r#"{"event":null,"auth_token":{}}"#);
+}
+
+#[tokio::test]
+async fn users_connect_to_their_own_hosts() {
+    let user_a = AuthUser::new(ADDRESS_A, "token-a");
+    let user_b = AuthUser::new(ADDRESS_B, "token-b");
+    let server = MockSocialServer::listen("127.0.0.1:0", &[user_a.clone(), user_b.clone()])
+        .await
+        .unwrap();
+    // Nothing listens on the shared host, bob falls back to the server after its first host
+    let environment = Environment {
+        name: "split".to_string(),
+        hosts: vec!["ws://127.0.0.1:1".to_string()],
+        users: BTreeMap::from([(
+            "bob".to_string(),
+            vec![
+                "ws://127.0.0.1:1".to_string(),
+                server.host().unwrap().to_string(),
+            ],
+        )]),
+    };
+    let mut users = Users::default();
+    users.insert("alice", user_a);
+    users.insert("bob", user_b);
+
+    let mut out = Vec::new();
+    parse("--as bob friends")
+        .unwrap()
+        .run(&users, &environment, &mut out)
+        .await
+        .unwrap();
+    assert!(out.is_empty());
+}
@@ -0,0 +1,152 @@
+use std::{collections::BTreeMap, env, sync::Mutex};
+
+use social_client::config::{
+    load_environment, ConfigError, Environment, CONFIG_FILE_VAR, ENVIRONMENT_VAR, HOSTS_VAR,
+};
+
+/// The tests read and write the same environment variables.
+static ENV: Mutex<()> = Mutex::new(());
+
+fn load(args: &str) -> (Result<Environment, ConfigError>, Vec<String>) {
+    let mut args: Vec<String> = args.split_whitespace().map(str::to_string).collect();
+    let environment = load_environment(&mut args);
+    (environment, args)
+}
+
+fn hosts(args: &str) -> Vec<String> {
+    load(args).0.unwrap().hosts
+}
+
+fn clear_vars() {
+    for var in [ENVIRONMENT_VAR, HOSTS_VAR, CONFIG_FILE_VAR] {
+        env::remove_var(var);
+    }
+}
+
+/// A config file unique to the test with a `staging` environment as the default one, and a
+/// `split` one where bob has its own hosts.
+fn config_file(name: &str) -> String {
+    let path = env::temp_dir().join(format!("social-client-{name}-{}.json", std::process::id()));
+    std::fs::write(
+        &path,
+        r#"{
+            "default_environment": "staging",
+            "environments": {
+                "staging": ["ws://localhost:6000", "ws://localhost:6001"],
+                "split": {
+                    "hosts": ["ws://localhost:6000", "ws://localhost:6001"],
+                    "users": { "bob": ["ws://localhost:7000", "ws://localhost:7001"] }
+                }
+            }
+        }"#,
+    )
+    .unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+#[test]
+fn local_environment_is_the_default() {
+    let _env = ENV.lock().unwrap();
+    clear_vars();
+
+    let (environment, args) = load("flow1");
+    let environment = environment.unwrap();
+    assert_eq!(environment.name, "local");
+    assert_eq!(environment.host(0), "ws://127.0.0.1:8085");
+    assert_eq!(environment.host(1), "ws://127.0.0.1:8086");
+    assert_eq!(environment.host(2), "ws://127.0.0.1:8085");
+    assert_eq!(args, ["flow1"]);
+}
+
+#[test]
+fn flags_take_precedence_over_variables_over_the_config_file() {
+    let _env = ENV.lock().unwrap();
+    clear_vars();
+    let config = config_file("precedence");
+
+    // The config file selects its default environment
+    let (environment, args) = load(&format!("--config {config} flow1"));
+    assert_eq!(environment.unwrap().name, "staging");
+    assert_eq!(args, ["flow1"]);
+    env::set_var(CONFIG_FILE_VAR, &config);
+    assert_eq!(hosts(""), ["ws://localhost:6000", "ws://localhost:6001"]);
+
+    // The environment variable selects another one, and the flag another one again
+    env::set_var(ENVIRONMENT_VAR, "tunnel");
+    assert_eq!(load("").0.unwrap().name, "tunnel");
+    let (environment, args) = load("--env zone flow1");
+    assert_eq!(
+        environment.unwrap(),
+        Environment {
+            name: "zone".to_string(),
+            hosts: vec!["wss://rpc-social-service.decentraland.zone".to_string()],
+            users: BTreeMap::new(),
+        }
+    );
+    assert_eq!(args, ["flow1"]);
+
+    // Hosts replace the ones of the environment
+    env::set_var(HOSTS_VAR, "ws://a, ws://b");
+    assert_eq!(hosts("--env zone"), ["ws://a", "ws://b"]);
+    assert_eq!(
+        hosts("--host ws://c --host ws://d,ws://e"),
+        ["ws://c", "ws://d", "ws://e"]
+    );
+
+    clear_vars();
+    std::fs::remove_file(config).unwrap();
+}
+
+#[test]
+fn invalid_selections_are_errors() {
+    let _env = ENV.lock().unwrap();
+    clear_vars();
+
+    assert!(matches!(
+        load("--env nowhere").0,
+        Err(ConfigError::UnknownEnvironment { name, .. }) if name == "nowhere"
+    ));
+    assert!(matches!(
+        load("flow1 --host").0,
+        Err(ConfigError::MissingValue("--host"))
+    ));
+    assert!(matches!(
+        load("--config /nonexistent/social-client.json").0,
+        Err(ConfigError::Io { .. })
+    ));
+    env::set_var(HOSTS_VAR, " , ");
+    assert!(matches!(load("").0, Err(ConfigError::NoHosts(_))));
+    clear_vars();
+}
+
+#[test]
+fn users_connect_to_their_own_hosts_or_else_in_turn() {
+    let _env = ENV.lock().unwrap();
+    clear_vars();
+    let config = config_file("users");
+
+    let environment = load(&format!("--config {config} --env split")).0.unwrap();
+    assert_eq!(
+        environment.user_hosts("bob", 0),
+        ["ws://localhost:7000", "ws://localhost:7001"]
+    );
+    assert_eq!(environment.user_hosts("alice", 0), ["ws://localhost:6000"]);
+    assert_eq!(environment.user_hosts("carol", 1), ["ws://localhost:6001"]);
+    assert_eq!(
+        environment.all_hosts(),
+        [
+            "ws://localhost:6000",
+            "ws://localhost:6001",
+            "ws://localhost:7000",
+            "ws://localhost:7001"
+        ]
+    );
+
+    // Hosts given explicitly replace those of the users too
+    let environment = load(&format!("--config {config} --env split --host ws://a"))
+        .0
+        .unwrap();
+    assert_eq!(environment.user_hosts("bob", 0), ["ws://a"]);
+
+    std::fs::remove_file(config).unwrap();
+}
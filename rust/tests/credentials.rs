@@ -0,0 +1,162 @@
+use std::{env, sync::Mutex};
+
+use social_client::credentials::{
+    load_users, parse_users, short_address, user_var, AuthUser, CredentialsError,
+    CredentialsOptions, CredentialsSource, Users, CREDENTIALS_VAR, STRICT_VAR, USERS_VAR,
+};
+
+/// The tests reading the credentials from environment variables share them.
+static ENV: Mutex<()> = Mutex::new(());
+
+#[test]
+fn missing_users_are_an_error() {
+    let mut users = Users::default();
+    assert!(matches!(
+        users.first::<1>(),
+        Err(CredentialsError::MissingUsers {
+            expected: 1,
+            found: 0,
+            ..
+        })
+    ));
+
+    users.insert("A", AuthUser::new("0xaa", "token-a"));
+    users.insert("B", AuthUser::new("0xbb", "token-b"));
+    let [user_a] = users.first().unwrap();
+    assert_eq!(user_a.address, "0xaa");
+    assert!(users.first::<3>().is_err());
+}
+
+#[test]
+fn short_addresses_are_the_end_of_the_address() {
+    assert_eq!(
+        short_address("0x00000000000000000000000000000000000000aa"),
+        "00aa"
+    );
+    assert_eq!(short_address("0xa"), "0xa");
+    assert_eq!(short_address(""), "");
+}
+
+#[tokio::test]
+async fn users_by_label_keep_the_file_order() {
+    let content = r#"{"users": {
+        "B": {"social_user_id": "0xbb", "access_token": "token-b"},
+        "A": {"social_user_id": "0xaa", "access_token": "token-a"},
+        "carol": {"social_user_id": "0xcc", "access_token": "token-c"}
+    }}"#;
+    let users = parse_users(content, "test", true).await.unwrap();
+    assert_eq!(users.labels(), ["B", "A", "carol"]);
+    let [first] = users.first().unwrap();
+    assert_eq!(first.address, "0xbb");
+}
+
+#[test]
+fn options_are_read_from_flags_over_variables() {
+    let _env = ENV.lock().unwrap();
+    let options = |args: &str| {
+        let mut args: Vec<String> = args.split_whitespace().map(str::to_string).collect();
+        let options = CredentialsOptions::from_args(&mut args);
+        (options, args)
+    };
+
+    env::set_var(CREDENTIALS_VAR, "credentials.test.json");
+    env::set_var(STRICT_VAR, "0");
+    let (parsed, args) = options("flow1");
+    assert_eq!(
+        parsed.unwrap(),
+        CredentialsOptions {
+            source: CredentialsSource::File("credentials.test.json".to_string()),
+            strict: false,
+        }
+    );
+    assert_eq!(args, ["flow1"]);
+
+    env::set_var(STRICT_VAR, "1");
+    let (parsed, args) = options("--credentials - flow1 --credentials-env");
+    assert_eq!(
+        parsed.unwrap(),
+        CredentialsOptions {
+            source: CredentialsSource::Env,
+            strict: true,
+        }
+    );
+    assert_eq!(args, ["flow1"]);
+    assert_eq!(
+        options("--strict --credentials - flow1").0.unwrap().source,
+        CredentialsSource::Stdin
+    );
+    assert!(matches!(
+        options("--credentials").0,
+        Err(CredentialsError::MissingValue("--credentials"))
+    ));
+
+    env::remove_var(CREDENTIALS_VAR);
+    env::remove_var(STRICT_VAR);
+}
+
+#[test]
+fn users_are_read_from_environment_variables() {
+    let _env = ENV.lock().unwrap();
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    env::set_var(USERS_VAR, "alice, bob");
+    env::set_var(user_var("alice", "ADDRESS"), "0xaa");
+    env::set_var(user_var("alice", "TOKEN"), "token-a");
+    env::set_var(user_var("bob", "ADDRESS"), "0xbb");
+    env::set_var(user_var("bob", "TOKEN"), "token-b");
+    let options = CredentialsOptions {
+        source: CredentialsSource::Env,
+        strict: true,
+    };
+
+    let users = runtime.block_on(load_users(&options, 2)).unwrap();
+    assert_eq!(users.labels(), ["alice", "bob"]);
+    let [alice, bob] = users.first().unwrap();
+    assert_eq!(
+        (alice.address.as_str(), alice.token.expose()),
+        ("0xaa", "token-a")
+    );
+    assert_eq!(
+        (bob.address.as_str(), bob.token.expose()),
+        ("0xbb", "token-b")
+    );
+
+    // Strict mode fails instead of prompting for what's missing
+    assert!(matches!(
+        runtime.block_on(load_users(&options, 3)),
+        Err(CredentialsError::MissingUsers {
+            expected: 3,
+            found: 2,
+            ..
+        })
+    ));
+    env::remove_var(user_var("bob", "TOKEN"));
+    assert!(matches!(
+        runtime.block_on(load_users(&options, 2)),
+        Err(CredentialsError::MissingField { label, field, .. })
+            if label == "bob" && field == "SOCIAL_CLIENT_USER_BOB_TOKEN"
+    ));
+
+    for var in [
+        USERS_VAR.to_string(),
+        user_var("alice", "ADDRESS"),
+        user_var("alice", "TOKEN"),
+        user_var("bob", "ADDRESS"),
+    ] {
+        env::remove_var(var);
+    }
+}
+
+#[tokio::test]
+async fn users_missing_a_field_are_rejected_in_strict_mode() {
+    let content = r#"{"users": [
+        {"social_user_id": "0xaa", "access_token": "token-a"},
+        {"access_token": "token-b"}
+    ]}"#;
+    assert!(matches!(
+        parse_users(content, "test", true).await,
+        Err(CredentialsError::MissingField { source, label, field })
+            if source == "test" && label == "B" && field == "social_user_id"
+    ));
+}
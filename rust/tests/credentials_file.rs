@@ -0,0 +1,64 @@
+use serde_json::json;
+use social_client::{
+    credentials_file::CredentialsFile,
+    encryption::{decrypt, encrypt, is_encrypted, EncryptionError},
+};
+
+#[test]
+fn encrypted_credentials_are_decrypted_with_the_passphrase() {
+    let content = r#"{"users": [{"social_user_id": "0xaa", "access_token": "secret-token"}]}"#;
+    let envelope = encrypt(content, "passphrase").unwrap();
+    assert!(is_encrypted(&envelope));
+    assert!(!envelope.to_string().contains("secret-token"));
+
+    assert_eq!(decrypt(&envelope, "passphrase").unwrap(), content);
+    assert!(matches!(
+        decrypt(&envelope, "wrong"),
+        Err(EncryptionError::WrongPassphrase)
+    ));
+}
+
+#[test]
+fn removing_a_user_keeps_the_labels_of_the_others() {
+    let mut file = CredentialsFile {
+        credentials: json!({ "users": [
+            { "social_user_id": "0xaa" },
+            { "social_user_id": "0xbb" },
+            { "social_user_id": "0xcc" },
+        ] }),
+        passphrase: None,
+    };
+
+    assert!(file.remove_user("A"));
+    assert!(!file.remove_user("A"));
+    file.add_user("B", json!({ "social_user_id": "0xb2" }));
+    file.add_user("D", json!({ "social_user_id": "0xdd" }));
+    assert_eq!(
+        file.credentials,
+        json!({ "users": [
+            { "label": "B", "social_user_id": "0xb2" },
+            { "label": "C", "social_user_id": "0xcc" },
+            { "label": "D", "social_user_id": "0xdd" },
+        ] })
+    );
+}
+
+#[test]
+fn removing_a_user_by_label_keeps_the_order_of_the_others() {
+    let mut file = CredentialsFile {
+        credentials: json!({ "users": {
+            "carol": { "social_user_id": "0xcc" },
+            "alice": { "social_user_id": "0xaa" },
+            "bob": { "social_user_id": "0xbb" },
+        } }),
+        passphrase: None,
+    };
+
+    assert!(file.remove_user("carol"));
+    let labels: Vec<&String> = file.credentials["users"]
+        .as_object()
+        .unwrap()
+        .keys()
+        .collect();
+    assert_eq!(labels, ["alice", "bob"]);
+}
@@ -0,0 +1,151 @@
+use std::{sync::Arc, time::Duration};
+
+use dcl_rpc::transports::memory::MemoryTransport;
+use social_client::{
+    client::SocialClient,
+    credentials::AuthUser,
+    event_stream::FriendshipEventStream,
+    friendship_state::FriendshipAction,
+    mock_server::MockSocialServer,
+    reconnect::{Backoff, ReconnectingClient},
+    FriendshipEventResponse,
+};
+
+const ADDRESS_A: &str = "0x00000000000000000000000000000000000000aa";
+const ADDRESS_B: &str = "0x00000000000000000000000000000000000000bb";
+const ADDRESS_C: &str = "0x00000000000000000000000000000000000000cc";
+
+/// The action of an event and the address of the other user, e.g. `Request 0x...`.
+fn action(event: &FriendshipEventResponse) -> String {
+    let (action, address) = FriendshipAction::from_event(event.body.as_ref().unwrap());
+    format!("{action:?} {}", address.unwrap())
+}
+
+struct Setup {
+    server: MockSocialServer,
+    client_a: SocialClient<MemoryTransport>,
+    client_b: SocialClient<MemoryTransport>,
+    client_c: SocialClient<MemoryTransport>,
+    events: FriendshipEventStream,
+}
+
+/// A mock server with three users and the event stream of A, subscribed.
+async fn setup() -> Setup {
+    let user_a = AuthUser::new(ADDRESS_A, "token-a");
+    let user_b = AuthUser::new(ADDRESS_B, "token-b");
+    let user_c = AuthUser::new(ADDRESS_C, "token-c");
+    let server = MockSocialServer::listen(
+        "127.0.0.1:0",
+        &[user_a.clone(), user_b.clone(), user_c.clone()],
+    )
+    .await
+    .unwrap();
+    let client_a = server.connect(user_a.clone()).await.unwrap();
+    let client_b = server.connect(user_b).await.unwrap();
+    let client_c = server.connect(user_c).await.unwrap();
+
+    let reconnecting = ReconnectingClient::new(server.host().unwrap(), user_a)
+        .with_request_timeout(Duration::from_millis(200))
+        .with_backoff(Backoff {
+            initial_delay: Duration::from_millis(10),
+            ..Default::default()
+        });
+    let mut events = FriendshipEventStream::new(Arc::new(reconnecting))
+        .with_idle_timeout(Duration::from_millis(100));
+    let subscribed = tokio::time::timeout(Duration::from_millis(300), events.next()).await;
+    assert!(subscribed.is_err(), "unexpected event {subscribed:?}");
+    Setup {
+        server,
+        client_a,
+        client_b,
+        client_c,
+        events,
+    }
+}
+
+#[tokio::test]
+async fn events_missed_while_disconnected_are_replayed() {
+    let Setup {
+        server,
+        client_a,
+        client_b,
+        client_c,
+        mut events,
+    } = setup().await;
+
+    // Become friends with B
+    client_a.request(ADDRESS_B, None).await.unwrap();
+    client_b.accept(ADDRESS_A).await.unwrap();
+    assert_eq!(
+        action(&events.next().await.unwrap()),
+        format!("Accept {ADDRESS_B}")
+    );
+
+    // B deletes the friendship and requests it again, and C sends a request, while disconnected
+    server.drop_connections().await;
+    client_b.delete(ADDRESS_A).await.unwrap();
+    client_b
+        .request(ADDRESS_A, Some("again".to_string()))
+        .await
+        .unwrap();
+    client_c.request(ADDRESS_A, None).await.unwrap();
+
+    let mut replayed = vec![];
+    for _ in 0..3 {
+        replayed.push(action(&events.next().await.unwrap()));
+    }
+    replayed.sort();
+    assert_eq!(
+        replayed,
+        [
+            format!("Delete {ADDRESS_B}"),
+            format!("Request {ADDRESS_B}"),
+            format!("Request {ADDRESS_C}"),
+        ]
+    );
+
+    // The stream goes on after re-subscribing
+    client_c.cancel(ADDRESS_A).await.unwrap();
+    assert_eq!(
+        action(&events.next().await.unwrap()),
+        format!("Cancel {ADDRESS_C}")
+    );
+}
+
+#[tokio::test]
+async fn events_like_a_reconciled_one_are_not_dropped_later() {
+    let Setup {
+        server,
+        client_a,
+        client_b,
+        mut events,
+        ..
+    } = setup().await;
+    client_a.request(ADDRESS_B, None).await.unwrap();
+    client_b.accept(ADDRESS_A).await.unwrap();
+    assert_eq!(
+        action(&events.next().await.unwrap()),
+        format!("Accept {ADDRESS_B}")
+    );
+
+    // B deletes the friendship while disconnected, which is replayed
+    server.drop_connections().await;
+    client_b.delete(ADDRESS_A).await.unwrap();
+    assert_eq!(
+        action(&events.next().await.unwrap()),
+        format!("Delete {ADDRESS_B}")
+    );
+
+    // Then they become friends again and B deletes the friendship again
+    client_a.request(ADDRESS_B, None).await.unwrap();
+    client_b.accept(ADDRESS_A).await.unwrap();
+    assert_eq!(
+        action(&events.next().await.unwrap()),
+        format!("Accept {ADDRESS_B}")
+    );
+    client_b.delete(ADDRESS_A).await.unwrap();
+    let deleted = tokio::time::timeout(Duration::from_secs(2), events.next())
+        .await
+        .expect("the second delete was dropped");
+    assert_eq!(action(&deleted.unwrap()), format!("Delete {ADDRESS_B}"));
+}
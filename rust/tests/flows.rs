@@ -0,0 +1,307 @@
+use std::time::Duration;
+
+use base64::Engine;
+
+use dcl_rpc::transports::memory::MemoryTransport;
+use social_client::{
+    client::SocialClient,
+    credentials::AuthUser,
+    error::SocialClientError,
+    friendship_event_response,
+    friendship_procedures::Flow,
+    friendship_state::{FriendshipAction, FriendshipState},
+    mock_server::MockSocialServer,
+    subscribe_friendship_events_updates_response, RequestEvents, ServerStreamResponse,
+    SubscribeFriendshipEventsUpdatesResponse,
+};
+
+const ADDRESS_A: &str = "0x00000000000000000000000000000000000000aa";
+const ADDRESS_B: &str = "0x00000000000000000000000000000000000000bb";
+
+/// How long to wait for a subscription event before assuming there are no more.
+const EVENT_TIMEOUT: Duration = Duration::from_millis(200);
+
+type Subscription = ServerStreamResponse<SubscribeFriendshipEventsUpdatesResponse>;
+
+struct Setup {
+    // Dropping the server stops it
+    _server: MockSocialServer,
+    user_a: AuthUser,
+    user_b: AuthUser,
+    client_a: SocialClient<MemoryTransport>,
+    client_b: SocialClient<MemoryTransport>,
+    updates_a: Subscription,
+    updates_b: Subscription,
+}
+
+async fn setup() -> Setup {
+    let user_a = AuthUser::new(ADDRESS_A, "token-a");
+    let user_b = AuthUser::new(ADDRESS_B, "token-b");
+    let server = MockSocialServer::start(&[user_a.clone(), user_b.clone()]);
+    let client_a = server.connect(user_a.clone()).await.unwrap();
+    let client_b = server.connect(user_b.clone()).await.unwrap();
+    let updates_a = client_a
+        .subscribe_friendship_events_updates()
+        .await
+        .unwrap();
+    let updates_b = client_b
+        .subscribe_friendship_events_updates()
+        .await
+        .unwrap();
+    Setup {
+        _server: server,
+        user_a,
+        user_b,
+        client_a,
+        client_b,
+        updates_a,
+        updates_b,
+    }
+}
+
+impl Setup {
+    async fn execute_flow(&self, flow: Flow) {
+        let responses = flow
+            .execute_flow(
+                self.client_a.module(),
+                self.client_b.module(),
+                self.user_a.clone(),
+                self.user_b.clone(),
+            )
+            .await
+            .unwrap();
+        assert!(!responses.is_empty());
+    }
+
+    async fn execute_event(&self, event: Flow) {
+        let module = match event {
+            Flow::Accept | Flow::Reject => self.client_b.module(),
+            _ => self.client_a.module(),
+        };
+        let response = event
+            .execute_event(module, self.user_a.clone(), self.user_b.clone())
+            .await
+            .unwrap();
+        assert!(response.is_some());
+    }
+
+    /// Assert the friends of both users.
+    async fn assert_friends(&self, friends_a: &[&str], friends_b: &[&str]) {
+        assert_eq!(
+            addresses(self.client_a.get_friends().await.unwrap()),
+            friends_a
+        );
+        assert_eq!(
+            addresses(self.client_b.get_friends().await.unwrap()),
+            friends_b
+        );
+    }
+
+    /// Assert the outgoing and incoming requests of both users.
+    async fn assert_requests(&self, requests_a: Requests<'_>, requests_b: Requests<'_>) {
+        let request_events_a = self.client_a.get_request_events().await.unwrap();
+        let request_events_b = self.client_b.get_request_events().await.unwrap();
+        assert_eq!(requests(&request_events_a), requests_a);
+        assert_eq!(requests(&request_events_b), requests_b);
+    }
+
+    /// Assert the events received by the subscription of each user, and that there are no more.
+    async fn assert_events(&mut self, events_a: &[Event<'_>], events_b: &[Event<'_>]) {
+        assert_eq!(next_events(&mut self.updates_a).await, events_a);
+        assert_eq!(next_events(&mut self.updates_b).await, events_b);
+    }
+}
+
+/// Outgoing and incoming requests by address.
+type Requests<'a> = (Vec<&'a str>, Vec<&'a str>);
+
+const NO_REQUESTS: Requests = (vec![], vec![]);
+
+/// The kind of a friendship event and the address of the other user.
+#[derive(Debug, PartialEq, Eq)]
+enum Event<'a> {
+    Request(&'a str),
+    Accept(&'a str),
+    Reject(&'a str),
+    Cancel(&'a str),
+    Delete(&'a str),
+}
+
+fn addresses(users: Vec<social_client::User>) -> Vec<String> {
+    users.into_iter().map(|user| user.address).collect()
+}
+
+fn requests(request_events: &RequestEvents) -> Requests<'_> {
+    (
+        request_addresses(&request_events.outgoing),
+        request_addresses(&request_events.incoming),
+    )
+}
+
+fn request_addresses(requests: &Option<social_client::Requests>) -> Vec<&str> {
+    requests
+        .iter()
+        .flat_map(|requests| &requests.items)
+        .filter_map(|request| request.user.as_ref())
+        .map(|user| user.address.as_str())
+        .collect()
+}
+
+async fn next_events(updates: &mut Subscription) -> Vec<Event<'static>> {
+    let mut events = vec![];
+    while let Ok(Some(update)) = tokio::time::timeout(EVENT_TIMEOUT, updates.next()).await {
+        let Some(subscribe_friendship_events_updates_response::Response::Events(responses)) =
+            update.response
+        else {
+            panic!("unexpected update {update:?}");
+        };
+        for response in responses.responses {
+            events.push(event(response.body.expect("event without body")));
+        }
+    }
+    events
+}
+
+fn event(body: friendship_event_response::Body) -> Event<'static> {
+    let address = |user: Option<social_client::User>| match user.expect("event without user") {
+        user if user.address == ADDRESS_A => ADDRESS_A,
+        user if user.address == ADDRESS_B => ADDRESS_B,
+        user => panic!("unexpected user {}", user.address),
+    };
+    match body {
+        friendship_event_response::Body::Request(request) => Event::Request(address(request.user)),
+        friendship_event_response::Body::Accept(accept) => Event::Accept(address(accept.user)),
+        friendship_event_response::Body::Reject(reject) => Event::Reject(address(reject.user)),
+        friendship_event_response::Body::Cancel(cancel) => Event::Cancel(address(cancel.user)),
+        friendship_event_response::Body::Delete(delete) => Event::Delete(address(delete.user)),
+    }
+}
+
+#[tokio::test]
+async fn flow1_request_and_cancel() {
+    let mut setup = setup().await;
+
+    setup.execute_flow(Flow::Flow1).await;
+
+    setup.assert_friends(&[], &[]).await;
+    setup.assert_requests(NO_REQUESTS, NO_REQUESTS).await;
+    setup
+        .assert_events(&[], &[Event::Request(ADDRESS_A), Event::Cancel(ADDRESS_A)])
+        .await;
+}
+
+#[tokio::test]
+async fn flow2_request_accept_and_delete_by_requester() {
+    let mut setup = setup().await;
+
+    setup.execute_flow(Flow::Flow2).await;
+
+    setup.assert_friends(&[], &[]).await;
+    setup.assert_requests(NO_REQUESTS, NO_REQUESTS).await;
+    setup
+        .assert_events(
+            &[Event::Accept(ADDRESS_B)],
+            &[Event::Request(ADDRESS_A), Event::Delete(ADDRESS_A)],
+        )
+        .await;
+}
+
+#[tokio::test]
+async fn flow3_request_and_reject() {
+    let mut setup = setup().await;
+
+    setup.execute_flow(Flow::Flow3).await;
+
+    setup.assert_friends(&[], &[]).await;
+    setup.assert_requests(NO_REQUESTS, NO_REQUESTS).await;
+    setup
+        .assert_events(&[Event::Reject(ADDRESS_B)], &[Event::Request(ADDRESS_A)])
+        .await;
+}
+
+#[tokio::test]
+async fn flow4_request_accept_and_delete_by_receiver() {
+    let mut setup = setup().await;
+
+    setup.execute_flow(Flow::Flow4).await;
+
+    setup.assert_friends(&[], &[]).await;
+    setup.assert_requests(NO_REQUESTS, NO_REQUESTS).await;
+    setup
+        .assert_events(
+            &[Event::Accept(ADDRESS_B), Event::Delete(ADDRESS_B)],
+            &[Event::Request(ADDRESS_A)],
+        )
+        .await;
+}
+
+#[tokio::test]
+async fn single_events_leave_intermediate_states() {
+    let mut setup = setup().await;
+
+    setup.execute_event(Flow::Request).await;
+
+    setup.assert_friends(&[], &[]).await;
+    setup
+        .assert_requests((vec![ADDRESS_B], vec![]), (vec![], vec![ADDRESS_A]))
+        .await;
+    setup.assert_events(&[], &[Event::Request(ADDRESS_A)]).await;
+
+    setup.execute_event(Flow::Accept).await;
+
+    setup.assert_friends(&[ADDRESS_B], &[ADDRESS_A]).await;
+    setup.assert_requests(NO_REQUESTS, NO_REQUESTS).await;
+    setup.assert_events(&[Event::Accept(ADDRESS_B)], &[]).await;
+}
+
+#[tokio::test]
+async fn invalid_events_are_rejected_before_reaching_the_server() {
+    let mut setup = setup().await;
+
+    // B has no request from A to accept
+    let result = Flow::Accept
+        .execute_event(
+            setup.client_b.module(),
+            setup.user_a.clone(),
+            setup.user_b.clone(),
+        )
+        .await;
+    assert!(
+        matches!(
+            &result,
+            Err(SocialClientError::InvalidTransition {
+                action: FriendshipAction::Accept,
+                state: FriendshipState::None,
+                address,
+            }) if address == ADDRESS_A
+        ),
+        "{result:?}"
+    );
+
+    // The mock server would have answered with a `BadRequestError` and notified A
+    setup.assert_friends(&[], &[]).await;
+    setup.assert_requests(NO_REQUESTS, NO_REQUESTS).await;
+    setup.assert_events(&[], &[]).await;
+}
+
+#[tokio::test]
+async fn events_with_an_expired_token_are_not_sent() {
+    let mut setup = setup().await;
+    let claims = serde_json::json!({ "exp": 1_000_000_000 }).to_string();
+    let expired = format!(
+        "e30.{}.signature",
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(claims)
+    );
+    let user_a = AuthUser::new(ADDRESS_A, &expired);
+
+    let result = Flow::Request
+        .execute_event(setup.client_a.module(), user_a, setup.user_b.clone())
+        .await;
+    assert!(
+        matches!(&result, Err(SocialClientError::TokenExpired { address, .. }) if address == ADDRESS_A),
+        "{result:?}"
+    );
+
+    setup.assert_requests(NO_REQUESTS, NO_REQUESTS).await;
+    setup.assert_events(&[], &[]).await;
+}
@@ -0,0 +1,119 @@
+use std::time::Duration;
+
+use social_client::{
+    credentials::AuthUser,
+    error::SocialClientError,
+    friendship_state::{FriendshipAction, FriendshipState},
+    mock_server::MockSocialServer,
+};
+
+const ADDRESS_A: &str = "0x00000000000000000000000000000000000000aa";
+const ADDRESS_B: &str = "0x00000000000000000000000000000000000000bb";
+
+const STATES: [FriendshipState; 4] = [
+    FriendshipState::None,
+    FriendshipState::PendingOutgoing,
+    FriendshipState::PendingIncoming,
+    FriendshipState::Friends,
+];
+
+const ACTIONS: [FriendshipAction; 5] = [
+    FriendshipAction::Request,
+    FriendshipAction::Accept,
+    FriendshipAction::Reject,
+    FriendshipAction::Cancel,
+    FriendshipAction::Delete,
+];
+
+/// The only transitions allowed, any other action is invalid in any other state.
+const TRANSITIONS: [(FriendshipState, FriendshipAction, FriendshipState); 5] = [
+    (
+        FriendshipState::None,
+        FriendshipAction::Request,
+        FriendshipState::PendingOutgoing,
+    ),
+    (
+        FriendshipState::PendingOutgoing,
+        FriendshipAction::Cancel,
+        FriendshipState::None,
+    ),
+    (
+        FriendshipState::PendingIncoming,
+        FriendshipAction::Accept,
+        FriendshipState::Friends,
+    ),
+    (
+        FriendshipState::PendingIncoming,
+        FriendshipAction::Reject,
+        FriendshipState::None,
+    ),
+    (
+        FriendshipState::Friends,
+        FriendshipAction::Delete,
+        FriendshipState::None,
+    ),
+];
+
+#[test]
+fn transitions_follow_the_table() {
+    for state in STATES {
+        for action in ACTIONS {
+            let expected = TRANSITIONS
+                .iter()
+                .find(|(from, by, _)| *from == state && *by == action)
+                .map(|(_, _, to)| *to);
+            assert_eq!(
+                state.transition(action),
+                expected,
+                "{action} from {state:?}"
+            );
+        }
+    }
+}
+
+#[test]
+fn invalid_transitions_are_errors() {
+    for state in STATES {
+        for action in ACTIONS {
+            match state.apply(action, "0xbb") {
+                Ok(next) => assert_eq!(state.transition(action), Some(next)),
+                Err(SocialClientError::InvalidTransition {
+                    action: rejected,
+                    address,
+                    state: from,
+                }) => {
+                    assert_eq!((rejected, address.as_str(), from), (action, "0xbb", state));
+                    assert_eq!(state.transition(action), None);
+                }
+                Err(err) => panic!("unexpected error {err}"),
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn waiting_for_a_state_never_reached_fails_with_the_last_one_seen() {
+    let user_a = AuthUser::new(ADDRESS_A, "token-a");
+    let user_b = AuthUser::new(ADDRESS_B, "token-b");
+    let server = MockSocialServer::start(&[user_a.clone(), user_b]);
+    let client_a = server.connect(user_a).await.unwrap();
+    client_a.request(ADDRESS_B, None).await.unwrap();
+
+    // B never accepts
+    let timeout = Duration::from_millis(700);
+    let result = client_a
+        .wait_for_friendship_state(ADDRESS_B, FriendshipState::Friends, timeout)
+        .await;
+    assert!(
+        matches!(
+            &result,
+            Err(SocialClientError::StateNotConverged {
+                address,
+                expected: FriendshipState::Friends,
+                observed: Some(FriendshipState::PendingOutgoing),
+                timeout: waited,
+            }) if address == ADDRESS_B && *waited == timeout
+        ),
+        "{result:?}"
+    );
+}
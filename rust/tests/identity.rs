@@ -0,0 +1,27 @@
+use social_client::identity::Wallet;
+
+// Test vectors from the web3.js accounts documentation
+const PRIVATE_KEY: &str = "0x4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+const ADDRESS: &str = "0x2c7536e3605d9c16a7a3d7b1898e529396a65c23";
+
+#[test]
+fn wallet_address_is_derived_from_the_private_key() {
+    let wallet = Wallet::from_private_key(PRIVATE_KEY).unwrap();
+    assert_eq!(wallet.address(), ADDRESS);
+    assert_eq!(wallet.private_key(), PRIVATE_KEY);
+}
+
+#[test]
+fn messages_are_signed_like_personal_sign() {
+    let wallet = Wallet::from_private_key(PRIVATE_KEY).unwrap();
+    assert_eq!(
+        wallet.sign_message("Some data").unwrap(),
+        "0xb91467e570a6466aa9e9876cbcd013baba02900b8979d43fe208a4a4f339f5fd6007e74cd82e037b800186422fc2da167c747ef045e5d18a5f5d4300f8e1a0291c"
+    );
+}
+
+#[test]
+fn invalid_private_key_is_rejected() {
+    assert!(Wallet::from_private_key("0x1234").is_err());
+    assert!(Wallet::from_private_key("not hex").is_err());
+}
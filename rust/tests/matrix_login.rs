@@ -0,0 +1,359 @@
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use serde_json::json;
+use social_client::{
+    credentials::{generate_credentials, parse_users, AuthUser, GenerateOptions},
+    encryption::is_encrypted,
+    identity::Wallet,
+    matrix::{MatrixError, MatrixLogin, LOGIN_PATH, REFRESH_PATH},
+    mock_server::MockSocialServer,
+    reconnect::ReconnectingClient,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+const ADDRESS: &str = "0x00000000000000000000000000000000000000aa";
+const PASSWORD: &str = "secret";
+
+/// A homeserver answering the login and refresh endpoints, issuing `token-1`, `token-2`...
+#[derive(Default)]
+struct StubHomeserver {
+    /// The user logged in last.
+    user: String,
+    /// The bodies of the wallet logins.
+    wallet_logins: Vec<serde_json::Value>,
+    issued: u32,
+    logins: u32,
+    refreshes: u32,
+    /// Lifetime of the issued tokens, `None` for tokens that never expire.
+    expires_in_ms: Option<u64>,
+    /// Answer like a proxy that can't reach the homeserver.
+    bad_gateway: bool,
+}
+
+impl StubHomeserver {
+    fn handle(&mut self, path: &str, body: &serde_json::Value) -> (u16, serde_json::Value) {
+        match path {
+            LOGIN_PATH if body["type"] == "m.login.decentraland" => {
+                self.wallet_logins.push(body.clone());
+                self.logins += 1;
+            }
+            LOGIN_PATH if body["password"] == PASSWORD => self.logins += 1,
+            LOGIN_PATH => {
+                return (
+                    403,
+                    json!({ "errcode": "M_FORBIDDEN", "error": "Invalid password" }),
+                )
+            }
+            REFRESH_PATH if body["refresh_token"] == format!("refresh-{}", self.issued) => {
+                self.refreshes += 1
+            }
+            REFRESH_PATH => {
+                return (
+                    401,
+                    json!({ "errcode": "M_UNKNOWN_TOKEN", "error": "Unknown refresh token" }),
+                )
+            }
+            _ => return (404, json!({ "errcode": "M_UNRECOGNIZED" })),
+        }
+        if let Some(user) = body["identifier"]["user"].as_str() {
+            self.user = user.to_string();
+        }
+        self.issued += 1;
+        (
+            200,
+            json!({
+                "user_id": format!("@{}:localhost", self.user),
+                "access_token": format!("token-{}", self.issued),
+                "refresh_token": format!("refresh-{}", self.issued),
+                "expires_in_ms": self.expires_in_ms,
+            }),
+        )
+    }
+}
+
+/// Serve the stub homeserver on a local port, returning its URL.
+async fn serve(homeserver: Arc<Mutex<StubHomeserver>>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(respond(stream, homeserver.clone()));
+        }
+    });
+    url
+}
+
+async fn respond(mut stream: TcpStream, homeserver: Arc<Mutex<StubHomeserver>>) {
+    let mut request = Vec::new();
+    let mut buffer = [0; 1024];
+    let (head, body_start) = loop {
+        let read = stream.read(&mut buffer).await.unwrap();
+        assert!(read > 0, "connection closed before the request was read");
+        request.extend_from_slice(&buffer[..read]);
+        if let Some(end) = request.windows(4).position(|window| window == b"\r\n\r\n") {
+            break (
+                String::from_utf8_lossy(&request[..end]).to_string(),
+                end + 4,
+            );
+        }
+    };
+    let content_length = head
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        .map(|(_, value)| value.trim().parse::<usize>().unwrap())
+        .unwrap_or(0);
+    while request.len() < body_start + content_length {
+        let read = stream.read(&mut buffer).await.unwrap();
+        request.extend_from_slice(&buffer[..read]);
+    }
+
+    let path = head.split(' ').nth(1).unwrap_or_default().to_string();
+    let body = serde_json::from_slice(&request[body_start..]).unwrap_or_default();
+    let (status, content_type, response) = {
+        let mut homeserver = homeserver.lock().unwrap();
+        if homeserver.bad_gateway {
+            (
+                502,
+                "text/html",
+                "<html><body>502 Bad Gateway</body></html>".to_string(),
+            )
+        } else {
+            let (status, response) = homeserver.handle(&path, &body);
+            (status, "application/json", response.to_string())
+        }
+    };
+    let message = format!(
+        "HTTP/1.1 {status} Stub\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{response}",
+        response.len()
+    );
+    stream.write_all(message.as_bytes()).await.unwrap();
+}
+
+/// A sessions file unique to the test, removed if it's left from a previous run.
+fn sessions_file(name: &str) -> PathBuf {
+    let path =
+        std::env::temp_dir().join(format!("social-client-{name}-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+#[tokio::test]
+async fn login_is_cached_across_instances() {
+    let homeserver = Arc::new(Mutex::new(StubHomeserver::default()));
+    let url = serve(homeserver.clone()).await;
+    let path = sessions_file("cache");
+    let path = path.to_str();
+
+    let session = MatrixLogin::new(&url, ADDRESS, PASSWORD)
+        .with_sessions_file(path)
+        .session()
+        .await
+        .unwrap();
+    assert_eq!(session.address(), ADDRESS);
+    assert_eq!(session.access_token.expose(), "token-1");
+
+    let cached = MatrixLogin::new(&url, ADDRESS, PASSWORD)
+        .with_sessions_file(path)
+        .session()
+        .await
+        .unwrap();
+    assert_eq!(cached, session);
+    assert_eq!(homeserver.lock().unwrap().logins, 1);
+}
+
+#[tokio::test]
+async fn sessions_are_cached_encrypted_with_a_passphrase() {
+    let homeserver = Arc::new(Mutex::new(StubHomeserver::default()));
+    let url = serve(homeserver.clone()).await;
+    let path = sessions_file("encrypted-cache");
+    let login = |passphrase| {
+        MatrixLogin::new(&url, ADDRESS, PASSWORD)
+            .with_sessions_file(path.to_str())
+            .with_sessions_passphrase(passphrase)
+    };
+
+    let session = login(Some("passphrase")).session().await.unwrap();
+    let cache = std::fs::read_to_string(&path).unwrap();
+    assert!(is_encrypted(&serde_json::from_str(&cache).unwrap()));
+    assert!(!cache.contains(session.access_token.expose()));
+    assert!(!cache.contains("refresh-1"));
+
+    let cached = login(Some("passphrase")).session().await.unwrap();
+    assert_eq!(cached, session);
+    assert_eq!(homeserver.lock().unwrap().logins, 1);
+
+    // Another passphrase can't read the cache, nor can a login without one
+    login(Some("other")).session().await.unwrap();
+    login(None).session().await.unwrap();
+    assert_eq!(homeserver.lock().unwrap().logins, 3);
+}
+
+#[tokio::test]
+async fn expired_session_is_refreshed() {
+    let homeserver = Arc::new(Mutex::new(StubHomeserver {
+        expires_in_ms: Some(0),
+        ..Default::default()
+    }));
+    let url = serve(homeserver.clone()).await;
+    let login = MatrixLogin::new(&url, ADDRESS, PASSWORD).with_sessions_file(None);
+
+    assert_eq!(
+        login.session().await.unwrap().access_token.expose(),
+        "token-1"
+    );
+    assert_eq!(
+        login.session().await.unwrap().access_token.expose(),
+        "token-2"
+    );
+    let homeserver = homeserver.lock().unwrap();
+    assert_eq!((homeserver.logins, homeserver.refreshes), (1, 1));
+}
+
+#[tokio::test]
+async fn wrong_password_is_rejected() {
+    let url = serve(Default::default()).await;
+    let err = MatrixLogin::new(&url, ADDRESS, "wrong")
+        .with_sessions_file(None)
+        .session()
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(&err, MatrixError::Rejected { status: 403, errcode, .. } if errcode == "M_FORBIDDEN"),
+        "{err}"
+    );
+}
+
+#[tokio::test]
+async fn proxy_errors_keep_their_status() {
+    let url = serve(Arc::new(Mutex::new(StubHomeserver {
+        bad_gateway: true,
+        ..Default::default()
+    })))
+    .await;
+    let err = MatrixLogin::new(&url, ADDRESS, PASSWORD)
+        .with_sessions_file(None)
+        .session()
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(&err, MatrixError::Rejected { status: 502, message, .. } if message == "Bad Gateway"),
+        "{err}"
+    );
+}
+
+#[tokio::test]
+async fn rejected_token_is_refreshed() {
+    let homeserver = Arc::new(Mutex::new(StubHomeserver::default()));
+    let url = serve(homeserver.clone()).await;
+    // The social service only accepts the token issued after the first one
+    let server = MockSocialServer::listen("127.0.0.1:0", &[AuthUser::new(ADDRESS, "token-2")])
+        .await
+        .unwrap();
+
+    let login = Arc::new(MatrixLogin::new(&url, ADDRESS, PASSWORD).with_sessions_file(None));
+    let user = login.auth_user().await.unwrap();
+    assert_eq!(user.token.expose(), "token-1");
+
+    let client = ReconnectingClient::new(server.host().unwrap(), user);
+    let friends = client
+        .call(|client| async move { client.get_friends().await })
+        .await
+        .unwrap();
+    assert!(friends.is_empty());
+    assert_eq!(client.user().token.expose(), "token-2");
+    assert_eq!(homeserver.lock().unwrap().refreshes, 1);
+}
+
+#[tokio::test]
+async fn wallet_login_sends_a_signed_auth_chain() {
+    let homeserver = Arc::new(Mutex::new(StubHomeserver::default()));
+    let url = serve(homeserver.clone()).await;
+    let wallet = Wallet::random();
+
+    let login = Arc::new(
+        MatrixLogin::with_wallet("http://unused", wallet.clone())
+            .with_login_url(&format!("{url}{LOGIN_PATH}"))
+            .with_sessions_file(None),
+    );
+    let user = login.auth_user().await.unwrap();
+    assert_eq!(user.address, wallet.address());
+
+    let body = homeserver.lock().unwrap().wallet_logins.pop().unwrap();
+    assert_eq!(body["identifier"]["user"], wallet.address());
+    let chain = body["auth_chain"].as_array().unwrap();
+    let link = |index: usize, field: &str| chain[index][field].as_str().unwrap().to_string();
+    assert_eq!(link(0, "type"), "SIGNER");
+    assert_eq!(link(0, "payload"), wallet.address());
+
+    // The wallet signed the ephemeral key, which signed the timestamp
+    assert_eq!(link(1, "type"), "ECDSA_EPHEMERAL");
+    assert_eq!(
+        recover_address(&link(1, "payload"), &link(1, "signature")),
+        wallet.address()
+    );
+    let ephemeral_address = link(1, "payload")
+        .lines()
+        .find_map(|line| line.strip_prefix("Ephemeral address: "))
+        .unwrap()
+        .to_string();
+    assert_eq!(link(2, "type"), "ECDSA_SIGNED_ENTITY");
+    assert_eq!(link(2, "payload"), body["timestamp"].as_str().unwrap());
+    assert_eq!(
+        recover_address(&link(2, "payload"), &link(2, "signature")),
+        ephemeral_address
+    );
+}
+
+/// The address that signed the message with `personal_sign`.
+fn recover_address(message: &str, signature: &str) -> String {
+    use k256::ecdsa::{RecoveryId, Signature, VerifyingKey};
+    use sha3::{Digest, Keccak256};
+
+    let signature = hex::decode(signature.strip_prefix("0x").unwrap()).unwrap();
+    let hash = Keccak256::digest(format!(
+        "\x19Ethereum Signed Message:\n{}{message}",
+        message.len()
+    ));
+    let key = VerifyingKey::recover_from_prehash(
+        &hash,
+        &Signature::from_slice(&signature[..64]).unwrap(),
+        RecoveryId::from_byte(signature[64] - 27).unwrap(),
+    )
+    .unwrap();
+    let public_key = key.to_encoded_point(false);
+    format!(
+        "0x{}",
+        hex::encode(&Keccak256::digest(&public_key.as_bytes()[1..])[12..])
+    )
+}
+
+#[tokio::test]
+async fn generated_credentials_are_loaded() {
+    let homeserver = Arc::new(Mutex::new(StubHomeserver::default()));
+    let url = serve(homeserver.clone()).await;
+    let options = GenerateOptions {
+        homeserver: Some(url),
+        log_in: true,
+        login_url: None,
+    };
+
+    let credentials = generate_credentials(3, &options).await.unwrap();
+    let users = parse_users(&credentials.to_string(), "generated", true)
+        .await
+        .unwrap();
+    assert_eq!(users.labels(), ["A", "B", "C"]);
+    for (index, (_, user)) in users.iter().enumerate() {
+        assert_eq!(user.address, credentials["users"][index]["social_user_id"]);
+        assert_eq!(user.token.expose(), format!("token-{}", index + 1));
+        assert!(user.token_refresher().is_some());
+    }
+    // The users were only logged in while being generated
+    assert_eq!(homeserver.lock().unwrap().wallet_logins.len(), 3);
+}
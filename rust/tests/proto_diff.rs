@@ -0,0 +1,243 @@
+use social_client::proto_diff::{Change, ElementKind, Label, ProtoContract};
+
+const OLD: &str = r#"
+syntax = "proto3";
+package decentraland.social.friendships;
+
+/* Users are identified
+   by their address */
+message User { string address = 1; }
+
+message Payload {
+  // For internal use only, subject to change.
+  optional string synapse_token = 1;
+}
+
+message Status {
+  enum Kind {
+    PENDING = 0;
+    FRIENDS = 1;
+  }
+  Kind kind = 1;
+  User user = 2 [deprecated = true];
+  oneof error {
+    string message = 3;
+  }
+}
+
+service FriendshipsService {
+  rpc GetFriends(Payload) returns (stream User) {}
+  rpc GetStatus(Payload) returns (Status);
+}
+"#;
+
+fn diff(new: &str) -> Vec<Change> {
+    let old = ProtoContract::parse(OLD).unwrap();
+    old.diff(&ProtoContract::parse(new).unwrap())
+}
+
+#[test]
+fn vendored_proto_is_parsed() {
+    let content =
+        std::fs::read_to_string("ext-proto/v1/decentraland/social/friendships/friendships.proto")
+            .unwrap();
+    let contract = ProtoContract::parse(&content).unwrap();
+    assert_eq!(
+        contract.package.as_deref(),
+        Some("decentraland.social.friendships")
+    );
+
+    let rpcs = &contract.services["FriendshipsService"].rpcs;
+    assert_eq!(rpcs.len(), 5);
+    assert_eq!(rpcs[0].name, "GetFriends");
+    assert_eq!(rpcs[0].request, "Payload");
+    assert!(!rpcs[0].request_stream);
+    assert_eq!(rpcs[0].response, "UsersResponse");
+    assert!(rpcs[0].response_stream);
+
+    let token = &contract.messages["Payload"].fields[0];
+    assert_eq!(token.name, "synapse_token");
+    assert_eq!(token.label, Label::Optional);
+    let oneof = &contract.messages["UsersResponse"].fields[4];
+    assert_eq!(oneof.number, 5);
+    assert_eq!(oneof.oneof.as_deref(), Some("response"));
+
+    assert!(contract.diff(&contract).is_empty());
+}
+
+#[test]
+fn field_changes_are_reported() {
+    let new = OLD
+        .replace("string address = 1;", "string address = 2;")
+        .replace("synapse_token = 1", "token = 1")
+        .replace("Kind kind = 1;", "Kind kind = 1;\n  int64 since = 4;")
+        .replace("string message = 3;\n  }", "}\n  string message = 3;");
+    let changes = diff(&new);
+    let printed: Vec<(String, bool)> = changes
+        .iter()
+        .map(|change| (change.to_string(), change.is_wire_incompatible()))
+        .collect();
+    assert_eq!(
+        printed,
+        [
+            (
+                "renamed field Payload.synapse_token to Payload.token".to_string(),
+                false
+            ),
+            (
+                "changed field Status.message: in oneof error -> not in a oneof".to_string(),
+                false
+            ),
+            ("added field Status.since = 4 (int64)".to_string(), false),
+            (
+                "changed field User.address: number 1 -> number 2".to_string(),
+                true
+            ),
+        ]
+    );
+}
+
+#[test]
+fn rpc_changes_are_wire_incompatible() {
+    let new = OLD
+        .replace("rpc GetFriends(", "rpc ListFriends(")
+        .replace("returns (Status)", "returns (stream Status)")
+        .replace(
+            "returns (stream Status);\n}",
+            "returns (stream Status);\n  rpc Subscribe(Payload) returns (stream Status) {}\n}",
+        );
+    let changes = diff(&new);
+    assert_eq!(
+        changes,
+        [
+            Change::Renamed {
+                kind: ElementKind::Rpc,
+                from: "FriendshipsService.GetFriends".to_string(),
+                to: "FriendshipsService.ListFriends".to_string(),
+                incompatible: true,
+            },
+            Change::Changed {
+                kind: ElementKind::Rpc,
+                element: "FriendshipsService.GetStatus".to_string(),
+                from: "(Payload) returns (Status)".to_string(),
+                to: "(Payload) returns (stream Status)".to_string(),
+                incompatible: true,
+            },
+            Change::Added {
+                kind: ElementKind::Rpc,
+                element: "FriendshipsService.Subscribe(Payload) returns (stream Status)"
+                    .to_string(),
+            },
+        ]
+    );
+}
+
+#[test]
+fn renamed_types_are_followed() {
+    let new = OLD
+        .replace("message User", "message Friend")
+        .replace("returns (stream User)", "returns (stream Friend)")
+        .replace("User user = 2", "Friend user = 2")
+        .replace("FRIENDS = 1", "ACCEPTED = 1;\n    BLOCKED = 2");
+    let changes = diff(&new);
+    let printed: Vec<String> = changes.iter().map(ToString::to_string).collect();
+    assert_eq!(
+        printed,
+        [
+            "renamed message User to Friend",
+            "renamed enum value Status.Kind.FRIENDS to Status.Kind.ACCEPTED",
+            "added enum value Status.Kind.BLOCKED = 2",
+        ]
+    );
+    assert!(changes.iter().all(|change| !change.is_wire_incompatible()));
+}
+
+#[test]
+fn nested_types_are_resolved_in_their_message() {
+    // `Kind` is `Status.Kind`, an enum, read as an int32 by the new version
+    let changes = diff(&OLD.replace("Kind kind = 1;", "int32 kind = 1;"));
+    let printed: Vec<(String, bool)> = changes
+        .iter()
+        .map(|change| (change.to_string(), change.is_wire_incompatible()))
+        .collect();
+    assert_eq!(
+        printed,
+        [(
+            "changed field Status.kind: Kind -> int32".to_string(),
+            false
+        )]
+    );
+
+    // The same type, written relatively, qualified or fully qualified
+    for written in [
+        "Status.Kind",
+        "friendships.Status.Kind",
+        ".decentraland.social.friendships.Status.Kind",
+    ] {
+        let new = OLD.replace("Kind kind = 1;", &format!("{written} kind = 1;"));
+        assert_eq!(diff(&new), [], "{written}");
+    }
+}
+
+#[test]
+fn only_single_fields_move_into_new_oneofs_safely() {
+    let moved = |new: &str| -> Vec<(String, bool)> {
+        diff(new)
+            .iter()
+            .map(|change| (change.to_string(), change.is_wire_incompatible()))
+            .collect()
+    };
+    // Into a new oneof alone
+    let new = OLD.replace(
+        "User user = 2",
+        "oneof who {\n    User user = 2;\n  }\n  int32 x = 4",
+    );
+    assert_eq!(
+        moved(&new),
+        [
+            (
+                "changed field Status.user: not in a oneof -> in oneof who".to_string(),
+                false
+            ),
+            ("added field Status.x = 4 (int32)".to_string(), false),
+        ]
+    );
+    // Into the existing oneof, next to `message`
+    let new = OLD.replace(
+        "User user = 2 [deprecated = true];\n  oneof error {",
+        "oneof error {\n    User user = 2;",
+    );
+    assert_eq!(
+        moved(&new),
+        [(
+            "changed field Status.user: not in a oneof -> in oneof error".to_string(),
+            true
+        )]
+    );
+    // Two fields into the same new oneof
+    let new = OLD.replace(
+        "Kind kind = 1;\n  User user = 2 [deprecated = true];",
+        "oneof state {\n    Kind kind = 1;\n    User user = 2;\n  }",
+    );
+    assert_eq!(
+        moved(&new),
+        [
+            (
+                "changed field Status.kind: not in a oneof -> in oneof state".to_string(),
+                true
+            ),
+            (
+                "changed field Status.user: not in a oneof -> in oneof state".to_string(),
+                true
+            ),
+        ]
+    );
+}
+
+#[test]
+fn parse_errors_have_the_line() {
+    let err = ProtoContract::parse("syntax = \"proto3\";\n\nmessage User {\n  string = 1;\n}")
+        .unwrap_err();
+    assert_eq!(err.line, 4);
+    assert_eq!(err.to_string(), "line 4: expected a name, found `=`");
+}
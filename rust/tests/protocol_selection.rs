@@ -0,0 +1,21 @@
+use social_client::protocol_selection::select_protocol;
+
+#[test]
+fn the_requested_version_or_else_the_newest_is_selected() {
+    assert_eq!(select_protocol(&["v1", "v2"], None), Ok(Some("v2")));
+    assert_eq!(select_protocol(&["v1", "v2"], Some("v1")), Ok(Some("v1")));
+    assert_eq!(select_protocol(&[], None), Ok(None));
+    assert_eq!(
+        select_protocol(&["v1"], Some("v2")),
+        Err("\"v2\" isn't one of the enabled versions: v1".to_string())
+    );
+}
+
+/// The build uses the version named by `SOCIAL_CLIENT_PROTOCOL` when built with it.
+#[test]
+fn the_build_uses_the_selected_version() {
+    let expected = option_env!("SOCIAL_CLIENT_PROTOCOL")
+        .filter(|version| !version.is_empty())
+        .unwrap_or("v1");
+    assert_eq!(social_client::PROTOCOL_VERSION, expected);
+}
@@ -0,0 +1,33 @@
+//! Each module built from `PROTOS` compiles, with its messages, the ones it imports and its client.
+
+use dcl_rpc::transports::memory::MemoryTransport;
+use prost::Message;
+
+/// Encoding and decoding a message gives it back.
+fn round_trip<M: Message + Default + PartialEq + std::fmt::Debug>(message: M) {
+    let decoded = M::decode(message.encode_to_vec().as_slice()).unwrap();
+    assert_eq!(decoded, message);
+}
+
+#[cfg(feature = "protocol-v1")]
+#[test]
+fn friendships_v1_compiles() {
+    use social_client::v1::{
+        friendship_event_payload, FriendshipEventPayload, FriendshipsServiceClient,
+        FriendshipsServiceClientDefinition, RequestPayload, UpdateFriendshipPayload, User,
+    };
+
+    round_trip(UpdateFriendshipPayload {
+        event: Some(FriendshipEventPayload {
+            body: Some(friendship_event_payload::Body::Request(RequestPayload {
+                user: Some(User {
+                    address: "0xaa".to_string(),
+                }),
+                message: Some("hi".to_string()),
+            })),
+        }),
+        auth_token: None,
+    });
+    fn client<C: FriendshipsServiceClientDefinition<MemoryTransport>>() {}
+    client::<FriendshipsServiceClient<MemoryTransport>>();
+}
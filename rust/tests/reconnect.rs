@@ -0,0 +1,83 @@
+use std::time::{Duration, Instant};
+
+use social_client::{
+    credentials::AuthUser,
+    error::SocialClientError,
+    mock_server::MockSocialServer,
+    reconnect::{Backoff, ReconnectingClient},
+};
+
+const ADDRESS: &str = "0x00000000000000000000000000000000000000aa";
+
+#[test]
+fn invalid_multipliers_use_the_max_delay() {
+    for multiplier in [-2.0, f64::NAN] {
+        let backoff = Backoff {
+            multiplier,
+            jitter: 0.0,
+            ..Default::default()
+        };
+        assert_eq!(backoff.delay(2), backoff.max_delay);
+    }
+}
+
+#[tokio::test]
+async fn calls_timing_out_give_up_after_the_max_attempts() {
+    let user = AuthUser::new(ADDRESS, "token");
+    let server = MockSocialServer::listen("127.0.0.1:0", std::slice::from_ref(&user))
+        .await
+        .unwrap();
+
+    let client = ReconnectingClient::new(server.host().unwrap(), user)
+        .with_request_timeout(Duration::from_millis(50))
+        .with_backoff(Backoff {
+            max_attempts: Some(2),
+            ..Default::default()
+        });
+    // The server never answers
+    let result = client
+        .call(|_| std::future::pending::<Result<(), SocialClientError>>())
+        .await;
+    assert!(
+        matches!(
+            &result,
+            Err(SocialClientError::ReconnectGaveUp { attempts: 2, last_error })
+                if matches!(**last_error, SocialClientError::Timeout(_))
+        ),
+        "{result:?}"
+    );
+}
+
+#[tokio::test]
+async fn failed_calls_are_retried_after_the_backoff_delay() {
+    let user = AuthUser::new(ADDRESS, "token");
+    let server = MockSocialServer::listen("127.0.0.1:0", std::slice::from_ref(&user))
+        .await
+        .unwrap();
+
+    let delay = Duration::from_millis(100);
+    let client = ReconnectingClient::new(server.host().unwrap(), user).with_backoff(Backoff {
+        initial_delay: delay,
+        multiplier: 1.0,
+        jitter: 0.0,
+        max_attempts: Some(3),
+        ..Default::default()
+    });
+    // Every call fails right away as if the connection was lost
+    let calls = std::sync::Mutex::new(Vec::new());
+    let result = client
+        .call(|_| {
+            calls.lock().unwrap().push(Instant::now());
+            std::future::ready(Err::<(), _>(SocialClientError::Timeout(delay)))
+        })
+        .await;
+    assert!(matches!(
+        result,
+        Err(SocialClientError::ReconnectGaveUp { attempts: 3, .. })
+    ));
+    let calls = calls.into_inner().unwrap();
+    assert_eq!(calls.len(), 3);
+    for retry in calls.windows(2) {
+        assert!(retry[1] - retry[0] >= delay, "{:?}", retry[1] - retry[0]);
+    }
+}
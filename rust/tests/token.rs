@@ -0,0 +1,115 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::{
+    engine::general_purpose::{STANDARD_NO_PAD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use serde_json::json;
+use social_client::{
+    credentials::AuthUser,
+    error::SocialClientError,
+    identity::{auth_chain_to_json, Wallet},
+    token::{check_token, check_tokens, inspect_token, TokenKind},
+};
+
+const ADDRESS: &str = "0x00000000000000000000000000000000000000aa";
+
+fn jwt(claims: serde_json::Value) -> String {
+    let encode = |value: serde_json::Value| URL_SAFE_NO_PAD.encode(value.to_string());
+    format!(
+        "{}.{}.signature",
+        encode(json!({ "alg": "ES256" })),
+        encode(claims)
+    )
+}
+
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+#[test]
+fn jwt_claims_are_decoded() {
+    let issued_at = SystemTime::now() - Duration::from_secs(60);
+    let expires_at = SystemTime::now() + Duration::from_secs(3600);
+    let token = jwt(json!({
+        "sub": format!("@{}:decentraland.zone", ADDRESS.to_uppercase().replace("0X", "0x")),
+        "iat": unix_seconds(issued_at),
+        "exp": unix_seconds(expires_at),
+    }));
+
+    let info = inspect_token(&token);
+    assert_eq!(info.kind, TokenKind::Jwt);
+    assert_eq!(info.subject.as_deref(), Some(ADDRESS));
+    assert_eq!(
+        info.expires_at.map(unix_seconds),
+        Some(unix_seconds(expires_at))
+    );
+    assert!(!info.is_expired());
+    assert!(info.warnings(ADDRESS).is_empty());
+}
+
+#[test]
+fn synapse_token_subject_is_decoded() {
+    let token = format!("syt_{}_RandomPart_0abc12", STANDARD_NO_PAD.encode(ADDRESS));
+    let info = inspect_token(&token);
+    assert_eq!(info.kind, TokenKind::Synapse);
+    assert_eq!(info.subject.as_deref(), Some(ADDRESS));
+    assert_eq!(info.expires_at, None);
+}
+
+#[test]
+fn auth_chain_signer_and_expiration_are_decoded() {
+    let wallet = Wallet::random();
+    let chain = wallet
+        .create_identity(Duration::from_secs(60))
+        .unwrap()
+        .sign("1700000000000")
+        .unwrap();
+    let info = inspect_token(&auth_chain_to_json(&chain).to_string());
+    assert_eq!(info.kind, TokenKind::AuthChain);
+    assert_eq!(info.subject, Some(wallet.address()));
+    assert_eq!(
+        info.issued_at,
+        Some(UNIX_EPOCH + Duration::from_millis(1_700_000_000_000))
+    );
+    // Expiring within the warning period
+    assert_eq!(info.warnings(&wallet.address()).len(), 1);
+}
+
+#[test]
+fn mismatched_subject_is_warned() {
+    let token = jwt(json!({ "sub": "0x00000000000000000000000000000000000000bb" }));
+    let user = AuthUser::new(ADDRESS, &token);
+    let warnings = check_tokens([&user]).unwrap();
+    assert_eq!(
+        warnings,
+        [format!(
+            "the token of {ADDRESS} was issued to 0x00000000000000000000000000000000000000bb"
+        )]
+    );
+}
+
+#[test]
+fn expired_token_is_refused() {
+    let token = jwt(json!({ "sub": ADDRESS, "exp": unix_seconds(SystemTime::now()) - 10 }));
+    let user = AuthUser::new(ADDRESS, &token);
+    assert!(matches!(
+        check_token(&user),
+        Err(SocialClientError::TokenExpired { address, .. }) if address == ADDRESS
+    ));
+}
+
+#[test]
+fn opaque_token_is_accepted() {
+    let info = check_token(&AuthUser::new(ADDRESS, "token-a")).unwrap();
+    assert_eq!(info.kind, TokenKind::Opaque);
+    assert_eq!(info.subject, None);
+}
+
+#[test]
+fn tokens_are_redacted() {
+    let user = AuthUser::new(ADDRESS, "syt_secret_token");
+    let printed = format!("{user:?} {}", user.token);
+    assert!(!printed.contains("syt_secret_token"), "{printed}");
+    assert_eq!(user.token.expose(), "syt_secret_token");
+}